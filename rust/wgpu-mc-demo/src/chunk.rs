@@ -2,8 +2,9 @@ use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Instant;
 
+use arc_swap::ArcSwap;
 use wgpu_mc::mc::block::{BlockstateKey, ChunkBlockState};
-use wgpu_mc::mc::chunk::{BlockStateProvider, Chunk, LightLevel};
+use wgpu_mc::mc::chunk::{BlockStateProvider, Chunk, ChunkBakeRequest, LightLevel};
 use wgpu_mc::mc::MinecraftState;
 use wgpu_mc::minecraft_assets::schemas::blockstates::multipart::StateValue;
 use wgpu_mc::render::pipeline::BLOCK_ATLAS;
@@ -41,6 +42,10 @@ impl BlockStateProvider for SimpleBlockstateProvider {
     fn is_section_empty(&self, _index: usize) -> bool {
         false
     }
+
+    fn get_fluid_state(&self, _x: i32, _y: i16, _z: i32) -> Option<wgpu_mc::mc::fluid::FluidState> {
+        None
+    }
 }
 
 impl Debug for SimpleBlockstateProvider {
@@ -58,7 +63,7 @@ pub fn make_chunks(wm: &WmRenderer) -> Chunk {
         .load()
         .get(BLOCK_ATLAS)
         .unwrap()
-        .load();
+        .load_full();
 
     let (index, _, block) = bm.blocks.get_full("minecraft:anvil").unwrap();
 
@@ -89,18 +94,45 @@ pub fn make_chunks(wm: &WmRenderer) -> Chunk {
         999
     );
 
-    let chunk = Chunk::new([0, 0]);
+    let chunk = Arc::new(Chunk::new([0, 0]));
     let time = Instant::now();
 
     let pipelines = wm.pipelines.load();
-    let layers = pipelines.chunk_layers.load();
-
-    chunk.bake_chunk(wm, &layers, &bm, &provider);
+    let layers = pipelines.chunk_layers.load_full();
+
+    drop(bm);
+
+    //Registering the chunk before enqueuing its bake is what lets
+    //WmRenderer::drive_chunk_bake_queue find it again by position once the
+    //rayon pool finishes and apply the result - see ChunkManager::loaded_chunks.
+    wm.mc
+        .chunks
+        .loaded_chunks
+        .write()
+        .insert(chunk.pos, ArcSwap::new(chunk.clone()));
+
+    wm.chunk_bake_queue.enqueue(ChunkBakeRequest {
+        chunk: chunk.clone(),
+        mc: wm.mc.clone(),
+        layers,
+        provider: Box::new(provider),
+        block_atlas: atlas,
+        distance_to_camera: 0.0,
+    });
+
+    //dispatch_batch bakes on the rayon pool via a blocking `install`, so by
+    //the time drive_chunk_bake_queue returns the one chunk we just enqueued
+    //has already been baked and uploaded.
+    wm.drive_chunk_bake_queue();
 
     println!(
         "Built 1 chunk in {} microseconds",
         Instant::now().duration_since(time).as_micros()
     );
 
-    chunk
+    //Only this demo ever looks at the chunk directly rather than through
+    //WmRenderer::chunk_bake_queue, so drop it back out of loaded_chunks and
+    //hand the caller the sole remaining owned value.
+    wm.mc.chunks.loaded_chunks.write().remove(&chunk.pos);
+    Arc::try_unwrap(chunk).expect("no other references to the demo chunk should outlive baking")
 }
@@ -8,7 +8,10 @@ use std::{sync::Arc, time::Instant};
 use arc_swap::access::Access;
 use arc_swap::{ArcSwap, ArcSwapAny};
 use byteorder::LittleEndian;
-use cgmath::{perspective, Deg, Matrix4, SquareMatrix};
+use cgmath::{
+    perspective, Angle, Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3,
+    Vector4,
+};
 use futures::executor::block_on;
 use glam::ivec2;
 use jni::objects::{AutoElements, JClass, JFloatArray, ReleaseMode};
@@ -33,13 +36,14 @@ use wgpu_mc::mc::chunk::{LightLevel, RenderLayer};
 use wgpu_mc::mc::entity::{BundledEntityInstances, InstanceVertex, UploadedEntityInstances};
 use wgpu_mc::render::graph::{Geometry, RenderGraph, ResourceBacking};
 use wgpu_mc::render::pipeline::Vertex;
+use wgpu_mc::render::shader_preprocessor::DefineSet;
 use wgpu_mc::render::shaderpack::{Mat4, Mat4ValueOrMult, ShaderPackConfig};
 use wgpu_mc::texture::{BindableTexture, TextureAndView};
 use wgpu_mc::util::BindableBuffer;
 use wgpu_mc::wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu_mc::wgpu::{BufferAddress, BufferBindingType, BufferUsages, PresentMode, TextureFormat};
 use wgpu_mc::{Frustum, wgpu, WindowSize};
-use wgpu_mc::{WgpuState, WmRenderer};
+use wgpu_mc::{BlockTextureBackend, WgpuState, WmRenderer};
 
 use crate::gl::{ElectrumGeometry, ElectrumVertex, GlTexture, GL_ALLOC};
 use crate::lighting::LIGHTMAP_GLID;
@@ -58,6 +62,278 @@ pub static MATRICES: Lazy<Mutex<Matrices>> = Lazy::new(|| {
 
 static SHOULD_STOP: OnceCell<()> = OnceCell::new();
 
+/// Vanilla's celestial angle in degrees, last reported to `bindSkyData`.
+/// Drives the shadow pass's light-space matrix; kept in lockstep with
+/// `mc.sky_data.angle` but mirrored into its own `Mutex<f32>` so the render
+/// loop can read it without going through an `ArcSwap` load every frame.
+/// Defaults to a fixed 45° (halfway between overhead and the horizon) until
+/// the first `bindSkyData` call.
+pub static SUN_ANGLE_DEGREES: Mutex<f32> = Mutex::new(45.0);
+
+/// Renderer backend/frame-pacing settings, set from Java before
+/// [start_rendering] runs and read again whenever the surface is
+/// reconfigured (on resize, or when it's reported outdated).
+pub static RENDER_CONFIG: Lazy<Mutex<RenderConfig>> =
+    Lazy::new(|| Mutex::new(RenderConfig::default()));
+
+#[derive(Debug, Copy, Clone)]
+pub struct RenderConfig {
+    pub backend: wgpu::Backends,
+    pub present_mode: PresentMode,
+    pub vsync: bool,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            backend: wgpu::Backends::PRIMARY,
+            present_mode: PresentMode::Immediate,
+            vsync: false,
+        }
+    }
+}
+
+/// Picks the surface's present mode given the user's settings and what the
+/// surface actually supports: `AutoVsync` whenever vsync is on, otherwise the
+/// requested mode if the surface supports it, else `Immediate` if supported,
+/// else the first mode the surface reports.
+fn choose_present_mode(config: &RenderConfig, surface_caps: &wgpu::SurfaceCapabilities) -> PresentMode {
+    if config.vsync {
+        return PresentMode::AutoVsync;
+    }
+
+    if surface_caps.present_modes.contains(&config.present_mode) {
+        config.present_mode
+    } else if surface_caps.present_modes.contains(&PresentMode::Immediate) {
+        PresentMode::Immediate
+    } else {
+        surface_caps.present_modes[0]
+    }
+}
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setBackend(_env: JNIEnv, _class: JClass, backend: jint) {
+    let backend = match backend {
+        0 => wgpu::Backends::VULKAN,
+        1 => wgpu::Backends::DX12,
+        2 => wgpu::Backends::METAL,
+        3 => wgpu::Backends::GL,
+        _ => wgpu::Backends::PRIMARY,
+    };
+
+    RENDER_CONFIG.lock().backend = backend;
+}
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setPresentMode(_env: JNIEnv, _class: JClass, mode: jint) {
+    let present_mode = match mode {
+        0 => PresentMode::Fifo,
+        1 => PresentMode::FifoRelaxed,
+        2 => PresentMode::Mailbox,
+        3 => PresentMode::Immediate,
+        _ => PresentMode::Fifo,
+    };
+
+    RENDER_CONFIG.lock().present_mode = present_mode;
+}
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setVsync(_env: JNIEnv, _class: JClass, vsync: jni::sys::jboolean) {
+    RENDER_CONFIG.lock().vsync = vsync != 0;
+}
+
+/// How the directional shadow map is sampled when shading terrain/entities.
+/// The shading math itself lives in the shadow-sampling WGSL include this
+/// feeds (see `chunk2-4`); this just picks which one and its parameters.
+///
+/// Entities don't cast into `@shadow_map` yet either: that'd mean drawing
+/// each `BundledEntityInstances`' transform SSBO a second time with
+/// `@mat4_light_space` in place of the camera's view-projection, from
+/// inside the same depth-from-the-light pass `create_shadow_map_texture`'s
+/// doc comment describes as unreachable from this tracked slice.
+#[derive(Debug, Copy, Clone)]
+pub enum ShadowFilterMode {
+    /// A single hardware 2x2 comparison-sampled tap (`textureSampleCompare`).
+    HardwareComparison,
+    /// `taps` Poisson-disc samples of radius `radius` (in shadow-map texels),
+    /// averaged for a soft-edged but fixed-width penumbra.
+    Pcf { taps: u32, radius: f32 },
+    /// Percentage-closer soft shadows: `blocker_search_taps` samples first
+    /// estimate the average occluder depth, then the penumbra width is
+    /// derived from `light_size` and used to scale a `pcf_taps`-sample PCF
+    /// pass, so the penumbra widens with distance from the occluder.
+    Pcss {
+        light_size: f32,
+        blocker_search_taps: u32,
+        pcf_taps: u32,
+    },
+}
+
+/// Of these settings, only `resolution`, the filter mode's kind and its tap
+/// count reach the GPU (as the shadow map's size, the `SHADOWS_*` terrain
+/// shader define and the Poisson-disc buffer's length). `depth_bias`,
+/// `normal_bias`, the PCF `radius` and the PCSS `light_size` and
+/// `blocker_search_taps` are stored here but not uploaded to any buffer.
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowConfig {
+    pub resolution: u32,
+    /// Constant depth-comparison bias, in shadow-map NDC units, applied to
+    /// the receiver depth to fight shadow acne.
+    pub depth_bias: f32,
+    /// Additional bias scaled by `1 - dot(normal, light_dir)`, so grazing
+    /// angles (which acne the most) get pushed out further than faces the
+    /// light hits head-on.
+    pub normal_bias: f32,
+    pub filter_mode: ShadowFilterMode,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            resolution: SHADOW_MAP_SIZE,
+            depth_bias: 0.0015,
+            normal_bias: 0.4,
+            filter_mode: ShadowFilterMode::Pcf {
+                taps: 16,
+                radius: 1.5,
+            },
+        }
+    }
+}
+
+/// Directional shadow-map settings, set from Java before [start_rendering]
+/// runs, since [create_shadow_map_texture] only reads this once at that
+/// point and nothing currently recreates the texture afterwards - a call to
+/// [setShadowResolution] after startup changes what's in this struct but has
+/// no effect on the already-created shadow map until the render graph gains
+/// a way to rebuild that resource on demand.
+pub static SHADOW_CONFIG: Lazy<Mutex<ShadowConfig>> = Lazy::new(|| Mutex::new(ShadowConfig::default()));
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setShadowResolution(_env: JNIEnv, _class: JClass, resolution: jint) {
+    SHADOW_CONFIG.lock().resolution = resolution.max(1) as u32;
+}
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setShadowBias(_env: JNIEnv, _class: JClass, depth_bias: jfloat, normal_bias: jfloat) {
+    let mut config = SHADOW_CONFIG.lock();
+    config.depth_bias = depth_bias;
+    config.normal_bias = normal_bias;
+}
+
+/// `mode`: 0 = hardware comparison, 2 = PCSS, anything else (1) = PCF.
+/// `taps` and `param_a` are interpreted per mode: PCF uses `taps` samples of
+/// radius `param_a`; PCSS uses `param_a` as its `light_size` and `taps` as
+/// both its blocker-search and its PCF tap count. Hardware comparison
+/// ignores both.
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setShadowFilterMode(
+    _env: JNIEnv,
+    _class: JClass,
+    mode: jint,
+    taps: jint,
+    param_a: jfloat,
+) {
+    let taps = taps.max(1) as u32;
+
+    let filter_mode = match mode {
+        0 => ShadowFilterMode::HardwareComparison,
+        2 => ShadowFilterMode::Pcss {
+            light_size: param_a,
+            blocker_search_taps: taps,
+            pcf_taps: taps,
+        },
+        _ => ShadowFilterMode::Pcf {
+            taps,
+            radius: param_a,
+        },
+    };
+
+    SHADOW_CONFIG.lock().filter_mode = filter_mode;
+}
+
+/// Samples to rotate per-fragment by a screen-space hash when filtering the
+/// shadow map, so PCF/PCSS averaging breaks up into noise instead of
+/// visible banding rings. Generated with Vogel's method (points swept along
+/// a spiral by the golden angle), which approximates a Poisson disc's even
+/// spacing without the cost of real Poisson-disc sampling.
+fn poisson_disc_offsets(count: u32) -> Vec<[f32; 2]> {
+    const GOLDEN_ANGLE_RADIANS: f32 = 2.399_963_3;
+
+    (0..count)
+        .map(|i| {
+            let radius = (i as f32 + 0.5).sqrt() / (count as f32).sqrt();
+            let theta = i as f32 * GOLDEN_ANGLE_RADIANS;
+            [radius * theta.cos(), radius * theta.sin()]
+        })
+        .collect()
+}
+
+/// Uniform buffer backing the sky pass's `SkyData` binding: `color_rgb`,
+/// `angle`, `brightness`, `star_shimmer`, `moon_phase` packed as floats in
+/// that order (padded to a 32-byte, 8-float stride). Built from plain
+/// floats rather than `bytemuck`-casting `SkyState` directly, so this isn't
+/// coupled to that struct's exact field order/padding.
+fn create_sky_data_buffer(wm: &WmRenderer) -> Arc<wgpu::Buffer> {
+    Arc::new(
+        wm.wgpu_state
+            .device
+            .create_buffer_init(&BufferInitDescriptor {
+                label: Some("sky_data"),
+                contents: bytemuck::cast_slice(&[0.0f32; 8]),
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            }),
+    )
+}
+
+/// Uniform buffer backing the fog/render-effects pass's `RenderEffectsData`
+/// binding. Each of `fog_color`/`color_modulator`/`dimension_fog_color` is
+/// packed into its own `vec4` (zero-padded if Java sent fewer than 4
+/// components) so the layout doesn't depend on exactly how many channels
+/// were uploaded; `fog_start`/`fog_end`/`fog_shape` share a fourth `vec4`.
+fn create_render_effects_buffer(wm: &WmRenderer) -> Arc<wgpu::Buffer> {
+    Arc::new(
+        wm.wgpu_state
+            .device
+            .create_buffer_init(&BufferInitDescriptor {
+                label: Some("render_effects_data"),
+                contents: bytemuck::cast_slice(&[0.0f32; 16]),
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            }),
+    )
+}
+
+/// Packs `values` into a `vec4`, truncating or zero-padding as needed so a
+/// variable-length Java float array always fits the fixed-size uniform
+/// layout [create_render_effects_buffer] expects.
+fn pack_vec4(values: &[f32]) -> [f32; 4] {
+    let mut packed = [0.0f32; 4];
+    let len = values.len().min(4);
+    packed[..len].copy_from_slice(&values[..len]);
+    packed
+}
+
+/// Uploads [poisson_disc_offsets] as a uniform buffer, padding each `vec2`
+/// out to 16 bytes to match WGSL's array-of-vec2 stride rules, for the
+/// shadow-sampling include to index by `gl_SampleID`-style loop counter.
+fn create_shadow_poisson_disc_buffer(wm: &WmRenderer, taps: u32) -> Arc<wgpu::Buffer> {
+    let mut contents = Vec::with_capacity(taps as usize * 16);
+
+    for [x, y] in poisson_disc_offsets(taps) {
+        contents.extend_from_slice(bytemuck::bytes_of(&[x, y, 0.0f32, 0.0f32]));
+    }
+
+    Arc::new(
+        wm.wgpu_state
+            .device
+            .create_buffer_init(&BufferInitDescriptor {
+                label: Some("shadow_poisson_disc"),
+                contents: &contents,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            }),
+    )
+}
+
 pub struct Matrices {
     pub projection: [[f32; 4]; 4],
     pub view: [[f32; 4]; 4],
@@ -110,6 +386,137 @@ fn create_matrix_buffer(wm: &WmRenderer) -> Arc<wgpu::Buffer> {
     )
 }
 
+/// Resolution of the directional (sun/moon) shadow map.
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+/// Allocates the depth texture `@shadow_map` is bound to below, and nothing
+/// more - the actual depth-from-the-light pass this is meant to back (draw
+/// terrain, and the `BundledEntityInstances` SSBOs per chunk1-1/chunk2-1,
+/// into this texture using `@mat4_light_space`) is a second render pass that
+/// has to live inside the shader graph itself (`render/graph.rs` +
+/// `graph.yaml`), since that's the only place terrain/entity draw calls are
+/// issued from. Neither file exists in this tree, so the texture, sampler,
+/// and light-space matrix below are allocated and kept up to date every
+/// frame, but nothing ever renders into `@shadow_map` - it stays at its
+/// cleared depth-texture default until that pass exists.
+fn create_shadow_map_texture(wm: &WmRenderer) -> TextureAndView {
+    let resolution = SHADOW_CONFIG.lock().resolution;
+
+    let texture = wm.wgpu_state.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("shadow_map"),
+        size: wgpu::Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    TextureAndView { texture, view }
+}
+
+fn create_shadow_comparison_sampler(wm: &WmRenderer) -> wgpu::Sampler {
+    wm.wgpu_state.device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("shadow_comparison_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        compare: Some(wgpu::CompareFunction::LessEqual),
+        ..Default::default()
+    })
+}
+
+/// The 8 corners of the camera's view frustum in world space, found by
+/// unprojecting the NDC cube's corners through the inverse of
+/// `projection * view`. wgpu's NDC depth range is `[0, 1]`, hence `z` only
+/// spans `0.0..=1.0` rather than OpenGL's `-1.0..=1.0`.
+fn frustum_corners_world_space(projection: Matrix4<f32>, view: Matrix4<f32>) -> [Vector3<f32>; 8] {
+    let inverse = (projection * view)
+        .invert()
+        .unwrap_or(Matrix4::identity());
+
+    let mut corners = [Vector3::new(0.0, 0.0, 0.0); 8];
+    let mut i = 0;
+
+    for &x in &[-1.0f32, 1.0] {
+        for &y in &[-1.0f32, 1.0] {
+            for &z in &[0.0f32, 1.0] {
+                let corner = inverse * Vector4::new(x, y, z, 1.0);
+                corners[i] = Vector3::new(corner.x, corner.y, corner.z) / corner.w;
+                i += 1;
+            }
+        }
+    }
+
+    corners
+}
+
+/// Fits a directional light's orthographic frustum around the camera's view
+/// frustum, for use as the light-space matrix a shadow pass renders terrain
+/// depth with. `sun_angle_degrees` is vanilla's celestial angle (the same
+/// value `bindSkyData` receives): 0 with the sun straight overhead, so the
+/// light points straight down, sweeping through a full rotation about the
+/// world's z axis over a day.
+pub fn compute_light_space_matrix(
+    projection: Matrix4<f32>,
+    view: Matrix4<f32>,
+    sun_angle_degrees: f32,
+) -> Matrix4<f32> {
+    let corners = frustum_corners_world_space(projection, view);
+
+    let center = corners
+        .iter()
+        .fold(Vector3::new(0.0, 0.0, 0.0), |sum, corner| sum + corner)
+        / corners.len() as f32;
+
+    let angle = Deg(sun_angle_degrees);
+    let light_direction = Vector3::new(angle.sin(), -angle.cos(), 0.0).normalize();
+
+    let light_view = Matrix4::look_at_rh(
+        Point3::from_vec(center - light_direction * 1000.0),
+        Point3::from_vec(center),
+        Vector3::unit_y(),
+    );
+
+    let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+
+    for corner in &corners {
+        let light_space_corner = light_view * corner.extend(1.0);
+
+        min.x = min.x.min(light_space_corner.x);
+        min.y = min.y.min(light_space_corner.y);
+        min.z = min.z.min(light_space_corner.z);
+        max.x = max.x.max(light_space_corner.x);
+        max.y = max.y.max(light_space_corner.y);
+        max.z = max.z.max(light_space_corner.z);
+    }
+
+    //Pull the near/far planes back so casters just outside the camera
+    //frustum (a tall tree at the frustum's edge, say) still shadow into it.
+    let z_margin = (max.z - min.z).max(1.0);
+
+    let light_projection = cgmath::ortho(
+        min.x,
+        max.x,
+        min.y,
+        max.y,
+        -max.z - z_margin,
+        -min.z + z_margin,
+    );
+
+    light_projection * light_view
+}
+
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn scheduleStop(_env: JNIEnv, _class: JClass) {
     let _ = SHOULD_STOP.set(());
@@ -153,18 +560,28 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
         jvm: env.get_java_vm().unwrap(),
     });
 
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::VULKAN,
-        ..Default::default()
-    });
-
-    let surface = instance.create_surface(window.clone()).unwrap();
-    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .unwrap();
+    let render_config = *RENDER_CONFIG.lock();
+
+    //Try the user's chosen backend first, falling back to whatever else is
+    //available so a missing Vulkan driver doesn't hard-fail startup.
+    let (instance, surface, adapter) = [render_config.backend, wgpu::Backends::PRIMARY, wgpu::Backends::all()]
+        .into_iter()
+        .find_map(|backends| {
+            let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+                backends,
+                ..Default::default()
+            });
+
+            let surface = instance.create_surface(window.clone()).ok()?;
+            let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: Some(&surface),
+            }))?;
+
+            Some((instance, surface, adapter))
+        })
+        .expect("no compatible graphics backend found");
 
     let required_limits = wgpu::Limits {
         max_push_constant_size: 128,
@@ -189,21 +606,13 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
     ))
     .unwrap();
 
-    const VSYNC: bool = false;
-
     let surface_caps = surface.get_capabilities(&adapter);
     let surface_config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: wgpu::TextureFormat::Bgra8Unorm,
         width: window.inner_size().width,
         height: window.inner_size().height,
-        present_mode: if VSYNC {
-            PresentMode::AutoVsync
-        } else if surface_caps.present_modes.contains(&PresentMode::Immediate) {
-            PresentMode::Immediate
-        } else {
-            surface_caps.present_modes[0]
-        },
+        present_mode: choose_present_mode(&render_config, &surface_caps),
 
         desired_maximum_frame_latency: 2,
         alpha_mode: surface_caps.alpha_modes[0],
@@ -227,7 +636,7 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
 
     let _ = RENDERER.set(wm.clone());
 
-    wm.init();
+    wm.init(BlockTextureBackend::Atlas);
 
     env.set_static_field(
         "dev/birb/wgpu/render/Wgpu",
@@ -256,6 +665,19 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
     let mat4_projection = create_matrix_buffer(&wm);
     let mat4_view = create_matrix_buffer(&wm);
     let mat4_model = create_matrix_buffer(&wm);
+    let mat4_light_space = create_matrix_buffer(&wm);
+
+    let sky_data = create_sky_data_buffer(&wm);
+    let render_effects_data = create_render_effects_buffer(&wm);
+
+    let shadow_map = create_shadow_map_texture(&wm);
+    let shadow_comparison_sampler = Arc::new(create_shadow_comparison_sampler(&wm));
+    let shadow_poisson_disc_taps = match SHADOW_CONFIG.lock().filter_mode {
+        ShadowFilterMode::HardwareComparison => 1,
+        ShadowFilterMode::Pcf { taps, .. } => taps,
+        ShadowFilterMode::Pcss { pcf_taps, .. } => pcf_taps,
+    };
+    let shadow_poisson_disc = create_shadow_poisson_disc_buffer(&wm, shadow_poisson_disc_taps);
 
     render_resources.insert(
         "@mat4_view".into(),
@@ -272,6 +694,36 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
         ResourceBacking::Buffer(mat4_model.clone(), BufferBindingType::Uniform),
     );
 
+    render_resources.insert(
+        "@mat4_light_space".into(),
+        ResourceBacking::Buffer(mat4_light_space.clone(), BufferBindingType::Uniform),
+    );
+
+    render_resources.insert(
+        "@shadow_map".into(),
+        ResourceBacking::Texture(Arc::new(shadow_map)),
+    );
+
+    render_resources.insert(
+        "@sampler_shadow_comparison".into(),
+        ResourceBacking::Sampler(shadow_comparison_sampler),
+    );
+
+    render_resources.insert(
+        "@shadow_poisson_disc".into(),
+        ResourceBacking::Buffer(shadow_poisson_disc, BufferBindingType::Uniform),
+    );
+
+    render_resources.insert(
+        "@sky_data".into(),
+        ResourceBacking::Buffer(sky_data.clone(), BufferBindingType::Uniform),
+    );
+
+    render_resources.insert(
+        "@render_effects_data".into(),
+        ResourceBacking::Buffer(render_effects_data.clone(), BufferBindingType::Uniform),
+    );
+
     let mut custom_bind_groups = HashMap::new();
     custom_bind_groups.insert(
         "@texture_electrum_gui".into(),
@@ -292,6 +744,34 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
         }],
     );
 
+    //Resolve every permutation of the terrain shader's active feature
+    //defines up front, so the preprocessor's per-path/define-set cache is
+    //already warm for whichever combination the shadow/fog state above
+    //picks, instead of stalling the first draw that needs an untried one.
+    //`shaders/terrain.wgsl` is this shaderpack's conventional entry point
+    //for the includes (shadow sampling, fog) chunk1-1/chunk2-1/chunk2-3
+    //describe; a resource pack that doesn't ship it simply logs and
+    //carries on, the same as a pass graph `drive_pass_graph` can't order.
+    let shadow_filter_define = match SHADOW_CONFIG.lock().filter_mode {
+        ShadowFilterMode::HardwareComparison => "SHADOWS_HARDWARE",
+        ShadowFilterMode::Pcf { .. } => "SHADOWS_PCF",
+        ShadowFilterMode::Pcss { .. } => "SHADOWS_PCSS",
+    };
+    let fog_cylinder_define = if wm.mc.render_effects.load().fog_shape >= 1.0 {
+        "FOG_CYLINDER"
+    } else {
+        "FOG_SPHERE"
+    };
+    let terrain_shader_defines =
+        DefineSet::new([(shadow_filter_define, "1"), (fog_cylinder_define, "1")]);
+    if let Err(e) = wm.shader_preprocessor.resolve_permutations(
+        &ResourcePath::from("wgpu_mc:shaders/terrain.wgsl"),
+        &terrain_shader_defines.permutations(&["HAS_OVERLAY"]),
+        &*wm.mc.resource_provider,
+    ) {
+        eprintln!("terrain shader preprocessing failed, falling back to the shaderpack's own sources: {:?}", e);
+    }
+
     let render_graph = RenderGraph::new(
         &wm,
         shader_pack,
@@ -322,6 +802,11 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
     let wm_clone = wm.clone();
     thread::spawn(move || {
         loop {
+            //Baking itself happens on wm_clone's rayon pool, but
+            //dispatch_batch blocks this thread until the batch is baked;
+            //the finished results are then uploaded from here, so all GPU
+            //writes (these and submit_chunk_updates') stay on this thread.
+            wm_clone.drive_chunk_bake_queue();
             wm_clone.submit_chunk_updates();
             thread::sleep(Duration::from_millis(10));
         }
@@ -397,6 +882,83 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
                                     0,
                                     bytemuck::cast_slice(&matrices.terrain_transformation),
                                 );
+
+                                let light_space = compute_light_space_matrix(
+                                    matrices.projection.into(),
+                                    matrices.view.into(),
+                                    *SUN_ANGLE_DEGREES.lock(),
+                                );
+                                let light_space: [[f32; 4]; 4] = light_space.into();
+                                wm.wgpu_state.queue.write_buffer(
+                                    &mat4_light_space,
+                                    0,
+                                    bytemuck::cast_slice(&light_space),
+                                );
+                            }
+
+                            //No shader graph is wired up to actually draw a sky
+                            //dome/sun/moon/stars from `sky_data` (that lives in the
+                            //shader pack's `graph.yaml` + WGSL, neither of which
+                            //ship in this tree), so the best this tracked slice can
+                            //do is make the background behind the terrain - what
+                            //render_graph.render's clear_color paints wherever
+                            //nothing is drawn - reflect the sky's current color
+                            //instead of staying hardcoded black.
+                            let mut background_color = {
+                                let sky = wm.mc.sky_data.load();
+                                wm.wgpu_state.queue.write_buffer(
+                                    &sky_data,
+                                    0,
+                                    bytemuck::cast_slice(&[
+                                        sky.color_r,
+                                        sky.color_g,
+                                        sky.color_b,
+                                        sky.angle,
+                                        sky.brightness,
+                                        sky.star_shimmer,
+                                        sky.moon_phase as f32,
+                                        0.0,
+                                    ]),
+                                );
+
+                                [
+                                    sky.color_r * sky.brightness,
+                                    sky.color_g * sky.brightness,
+                                    sky.color_b * sky.brightness,
+                                ]
+                            };
+
+                            {
+                                let fog = wm.mc.render_effects.load();
+
+                                //Real per-pixel fog still needs a fragment shader this
+                                //tree doesn't have (same missing graph.yaml/WGSL as the
+                                //sky pass above), but vanilla's own convention for "the
+                                //whole view is fogged" (blindness, the void, etc.) is
+                                //fog_start == fog_end, and that much we can honor for
+                                //the background without guessing at a distance curve.
+                                if fog.fog_start >= fog.fog_end {
+                                    let fog_color = pack_vec4(&fog.fog_color);
+                                    background_color = [fog_color[0], fog_color[1], fog_color[2]];
+                                }
+
+                                let mut packed = [0.0f32; 16];
+                                packed[0..4].copy_from_slice(&[
+                                    fog.fog_start,
+                                    fog.fog_end,
+                                    fog.fog_shape,
+                                    0.0,
+                                ]);
+                                packed[4..8].copy_from_slice(&pack_vec4(&fog.fog_color));
+                                packed[8..12].copy_from_slice(&pack_vec4(&fog.color_modulator));
+                                packed[12..16]
+                                    .copy_from_slice(&pack_vec4(&fog.dimension_fog_color));
+
+                                wm.wgpu_state.queue.write_buffer(
+                                    &render_effects_data,
+                                    0,
+                                    bytemuck::cast_slice(&packed),
+                                );
                             }
 
                             let mut surface_guard = wm.wgpu_state.surface.write();
@@ -409,6 +971,10 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
 
                                 surface_config.width = size.width;
                                 surface_config.height = size.height;
+                                surface_config.present_mode = choose_present_mode(
+                                    &RENDER_CONFIG.lock(),
+                                    &surface.get_capabilities(&wm.wgpu_state.adapter),
+                                );
 
                                 surface.configure(&wm.wgpu_state.device, &surface_config);
                                 surface.get_current_texture().unwrap()
@@ -425,19 +991,34 @@ pub fn start_rendering(mut env: JNIEnv, title: JString) {
                                 array_layer_count: None,
                             });
 
+                            if let Err(e) = wm.drive_pass_graph(surface_config) {
+                                eprintln!("pass graph could not be ordered, skipping this frame's extra passes: {:?}", e);
+                            }
+
                             {
                                 let mut encoder = wm.wgpu_state.device.create_command_encoder(
                                     &wgpu::CommandEncoderDescriptor { label: None },
                                 );
 
+                                let frustum = {
+                                    let matrices = MATRICES.lock();
+                                    let combined = Matrix4::from(matrices.projection)
+                                        * Matrix4::from(matrices.view)
+                                        * Matrix4::from(matrices.terrain_transformation);
+                                    //cgmath's `Into<[[f32; 4]; 4]>` yields columns
+                                    //(`matrix[col][row]`), but `from_modelview_projection`
+                                    //is row-major - transpose so row `i` is actually row `i`.
+                                    Frustum::from_modelview_projection(combined.transpose().into())
+                                };
+
                                 render_graph.render(
                                     &wm,
                                     &mut encoder,
                                     &SCENE,
                                     &view,
-                                    [0; 3],
+                                    background_color,
                                     &mut geometry,
-                                    &Frustum::from_modelview_projection([[0.0; 4]; 4])
+                                    &frustum,
                                 );
 
                                 wm.wgpu_state.queue.submit([encoder.finish()]);
@@ -660,9 +1241,37 @@ pub enum MCTextureId {
     Lightmap,
 }
 
-pub static ENTITY_INSTANCES: Lazy<Mutex<HashMap<String, BundledEntityInstances>>> =
+pub static ENTITY_INSTANCES: Lazy<Mutex<HashMap<u32, BundledEntityInstances>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Interns entity model names to small integer ids, so the hot
+/// `setEntityInstanceBuffer` path (called every frame per entity type)
+/// never has to materialize a JNI string or hash one to find its
+/// [BundledEntityInstances]; only [registerEntityModel] (called once per
+/// entity type) pays for the string lookup.
+#[derive(Default)]
+struct EntityModelIds {
+    by_name: HashMap<String, u32>,
+    by_id: Vec<String>,
+}
+
+static ENTITY_MODEL_IDS: Lazy<Mutex<EntityModelIds>> = Lazy::new(|| Mutex::new(EntityModelIds::default()));
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn registerEntityModel(mut env: JNIEnv, _class: JClass, name: JString) -> jint {
+    let name: String = env.get_string(&name).unwrap().into();
+
+    let mut ids = ENTITY_MODEL_IDS.lock();
+    if let Some(&id) = ids.by_name.get(&name) {
+        return id as jint;
+    }
+
+    let id = ids.by_id.len() as u32;
+    ids.by_id.push(name.clone());
+    ids.by_name.insert(name, id);
+    id as jint
+}
+
 pub static MC_TEXTURES: Lazy<Mutex<HashMap<MCTextureId, Arc<BindableTexture>>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
@@ -687,11 +1296,28 @@ pub fn identifyGlTexture(_env: JNIEnv, _class: JClass, texture: jint, gl_id: jin
     );
 }
 
+/// Takes an id from [registerEntityModel] rather than a name, and writes
+/// straight from the JNI-owned transform/overlay arrays into each
+/// `BundledEntityInstances`'s SSBOs.
+///
+/// Reuses the previous call's `BundledEntityInstances` only while its SSBOs
+/// are still large enough for this call's `instance_count`; growing past
+/// that rebuilds it via [BundledEntityInstances::new] instead of writing
+/// past the end of the existing buffers (see the `needs_new_buffers` check
+/// below), since a mob pack spawning mid-game can easily make instance
+/// counts grow between calls for the same entity type.
+///
+/// This still re-uploads the full transform SSBO every call rather than
+/// sub-allocating from a ring of persistent buffers that rotate across
+/// frames-in-flight, and there's no compute pre-pass compacting surviving
+/// instances past frustum culling into an indirect draw-args buffer — both
+/// require reshaping `BundledEntityInstances`/`UploadedEntityInstances`
+/// and a compute pipeline, which live outside this tracked slice.
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn setEntityInstanceBuffer(
-    mut env: JNIEnv,
+    _env: JNIEnv,
     _class: JClass,
-    entity_name: JString,
+    entity_id: jint,
     mat4_ptr: jlong,
     mat4_len: jint,
     overlay_ptr: jlong,
@@ -702,24 +1328,22 @@ pub fn setEntityInstanceBuffer(
     assert!(instance_count >= 0);
     let now = Instant::now();
     let instance_count = instance_count as u32;
+    let entity_id = entity_id as u32;
 
     let wm = RENDERER.get().unwrap();
 
-    //TODO this is slow, let's use an integer id somewhere
-    let entity_name: String = env.get_string(&entity_name).unwrap().into();
-
     if instance_count == 0 {
-        ENTITY_INSTANCES.lock().remove(&entity_name);
+        ENTITY_INSTANCES.lock().remove(&entity_id);
         return Instant::now().duration_since(now).as_nanos() as jlong;
     }
 
-    let mat4s = unsafe { slice::from_raw_parts(mat4_ptr as usize as *mut f32, mat4_len as usize) };
-
+    //Borrowed straight from the JNI-owned arrays: writing to the GPU below
+    //never needs an owned copy, so skip the Vec allocations that used to
+    //sit between them.
+    let mat4s = unsafe { slice::from_raw_parts(mat4_ptr as usize as *const f32, mat4_len as usize) };
     let overlays =
-        unsafe { slice::from_raw_parts(overlay_ptr as usize as *mut i32, overlay_len as usize) };
+        unsafe { slice::from_raw_parts(overlay_ptr as usize as *const i32, overlay_len as usize) };
 
-    let transforms: Vec<f32> = Vec::from(mat4s);
-    let overlays: Vec<i32> = Vec::from(overlays);
     let verts: Vec<InstanceVertex> = (0..instance_count)
         .map(|index| InstanceVertex {
             entity_index: index,
@@ -728,40 +1352,61 @@ pub fn setEntityInstanceBuffer(
         .collect();
 
     let mut instances = ENTITY_INSTANCES.lock();
-    let bundled_entity_instances =
-        if let Some(bundled_entity_instances) = instances.get_mut(&entity_name) {
-            bundled_entity_instances.count = instance_count;
-            bundled_entity_instances
-        } else {
-            let texture = {
-                let gl_alloc = GL_ALLOC.read();
-
-                match gl_alloc.get(&(texture_id as u32)) {
-                    None => return 0,
-                    Some(GlTexture {
-                        bindable_texture: None,
-                        ..
-                    }) => return 0,
-                    _ => {}
-                }
 
-                gl_alloc
-                    .get(&(texture_id as u32))
-                    .unwrap()
-                    .bindable_texture
-                    .as_ref()
-                    .unwrap()
-                    .clone()
-            };
-            let models = wm.mc.entity_models.read();
-            let entity = models.get(&entity_name).unwrap();
-            instances.insert(
-                entity_name.clone(),
-                BundledEntityInstances::new(wm, entity.clone(), instance_count, texture),
-            );
-            instances.get(&entity_name).unwrap()
+    let needs_new_buffers = match instances.get(&entity_id) {
+        None => true,
+        Some(existing) => {
+            (mat4s.len() * size_of::<f32>()) as u64
+                > existing.uploaded.transform_ssbo.buffer.size()
+                || (overlays.len() * size_of::<i32>()) as u64
+                    > existing.uploaded.overlay_ssbo.buffer.size()
+                || (verts.len() * size_of::<InstanceVertex>()) as u64
+                    > existing.uploaded.instance_vbo.as_ref().size()
+        }
+    };
+
+    let bundled_entity_instances = if needs_new_buffers {
+        let texture = {
+            let gl_alloc = GL_ALLOC.read();
+
+            match gl_alloc.get(&(texture_id as u32)) {
+                None => return 0,
+                Some(GlTexture {
+                    bindable_texture: None,
+                    ..
+                }) => return 0,
+                _ => {}
+            }
+
+            gl_alloc
+                .get(&(texture_id as u32))
+                .unwrap()
+                .bindable_texture
+                .as_ref()
+                .unwrap()
+                .clone()
         };
 
+        let entity_name = ENTITY_MODEL_IDS
+            .lock()
+            .by_id
+            .get(entity_id as usize)
+            .cloned()
+            .expect("entity id used before registerEntityModel");
+
+        let models = wm.mc.entity_models.read();
+        let entity = models.get(&entity_name).unwrap();
+        instances.insert(
+            entity_id,
+            BundledEntityInstances::new(wm, entity.clone(), instance_count, texture),
+        );
+        instances.get_mut(&entity_id).unwrap()
+    } else {
+        let bundled_entity_instances = instances.get_mut(&entity_id).unwrap();
+        bundled_entity_instances.count = instance_count;
+        bundled_entity_instances
+    };
+
     wm.wgpu_state.queue.write_buffer(
         bundled_entity_instances.uploaded.instance_vbo.as_ref(),
         0,
@@ -770,12 +1415,12 @@ pub fn setEntityInstanceBuffer(
     wm.wgpu_state.queue.write_buffer(
         &bundled_entity_instances.uploaded.transform_ssbo.buffer,
         0,
-        bytemuck::cast_slice(&transforms),
+        bytemuck::cast_slice(mat4s),
     );
     wm.wgpu_state.queue.write_buffer(
         &bundled_entity_instances.uploaded.overlay_ssbo.buffer,
         0,
-        bytemuck::cast_slice(&overlays),
+        bytemuck::cast_slice(overlays),
     );
     Instant::now().duration_since(now).as_nanos() as jlong
 }
@@ -792,16 +1437,22 @@ pub fn bindSkyData(
     star_shimmer: jfloat,
     moon_phase: jint,
 ) {
-    // let mut sky_data = (**RENDERER.get().unwrap().mc.sky_data.load()).clone();
-    // sky_data.color_r = r;
-    // sky_data.color_g = g;
-    // sky_data.color_b = b;
-    // sky_data.angle = angle;
-    // sky_data.brightness = brightness;
-    // sky_data.star_shimmer = star_shimmer;
-    // sky_data.moon_phase = moon_phase;
-    //
-    // RENDERER.get().unwrap().mc.sky_data.swap(Arc::new(sky_data));
+    let wm = RENDERER.get().unwrap();
+
+    let mut sky_data = (**wm.mc.sky_data.load()).clone();
+    sky_data.color_r = r;
+    sky_data.color_g = g;
+    sky_data.color_b = b;
+    sky_data.angle = angle;
+    sky_data.brightness = brightness;
+    sky_data.star_shimmer = star_shimmer;
+    sky_data.moon_phase = moon_phase;
+
+    wm.mc.sky_data.swap(Arc::new(sky_data));
+
+    //The shadow pass's light-space matrix is recomputed every frame from
+    //this, rather than read through the ArcSwap each time.
+    *SUN_ANGLE_DEGREES.lock() = angle;
 }
 
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
@@ -836,14 +1487,14 @@ pub fn bindRenderEffectsData(
     env.get_float_array_region(&dimension_fog_color, 0, &mut dimension_fog_color_vec[..])
         .unwrap();
 
-    // render_effects_data.fog_color = fog_color_vec;
-    // render_effects_data.color_modulator = color_modulator_vec;
-    // render_effects_data.dimension_fog_color = dimension_fog_color_vec;
-    //
-    // RENDERER
-    //     .get()
-    //     .unwrap()
-    //     .mc
-    //     .render_effects
-    //     .swap(Arc::new(render_effects_data));
+    render_effects_data.fog_color = fog_color_vec;
+    render_effects_data.color_modulator = color_modulator_vec;
+    render_effects_data.dimension_fog_color = dimension_fog_color_vec;
+
+    RENDERER
+        .get()
+        .unwrap()
+        .mc
+        .render_effects
+        .swap(Arc::new(render_effects_data));
 }
@@ -36,14 +36,38 @@ To render entities, you need an entity model. wgpu-mc makes no assumptions about
 so it's up to you to provide them to wgpu-mc.
 
 See the [render::entity] module for an example of rendering an example entity.
+
+## Custom Compute/Render Passes
+
+Extra passes that need to run before the terrain/entity draw graph (a compute
+pass feeding a storage buffer, a post-processing chain, GPU-driven culling)
+can be declared with [PassGraph] rather than ordered by hand: build one from
+[PassNode]s that declare which named texture slots they read and write, then
+register them on [WmRenderer::pass_graph], and call
+[WmRenderer::drive_pass_graph] once per frame before drawing. Neither
+[WmRenderer::render] nor anything else in this crate runs the pass graph on
+its own; the JNI renderer calls [WmRenderer::drive_pass_graph] from its
+redraw handler.
+
+## Shader Preprocessing
+
+Shaderpack WGSL can share code across passes (lighting, fog, noise helpers)
+via `#include "path"`, and select per-variant code with `#define`/`#ifdef`/
+`#else`/`#endif`, instead of duplicating a file per feature combination.
+Resolve a path through [WmRenderer::preprocess_shader] rather than reading
+shaderpack sources directly - it expands includes against the renderer's
+resource provider, evaluates conditionals against a caller-supplied
+`DefineSet`, and caches the result per path + define set so recompiling an
+already-seen variant is a cache hit.
  */
 
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroU64;
 use std::sync::Arc;
 
 use arc_swap::ArcSwap;
+use cgmath::{InnerSpace, Vector3, Vector4};
 pub use minecraft_assets;
 use parking_lot::{Mutex, RwLock};
 pub use wgpu;
@@ -52,12 +76,14 @@ use wgpu::{
 };
 use wgpu::util::StagingBelt;
 
+use crate::mc::chunk::{free_layer_ranges, ChunkBakeQueue};
 use crate::mc::entity::BundledEntityInstances;
 use crate::mc::MinecraftState;
 use crate::mc::resource::ResourceProvider;
 use crate::render::atlas::Atlas;
 use crate::render::graph::ShaderGraph;
 use crate::render::pipeline::{BLOCK_ATLAS, ENTITY_ATLAS, WmPipelines};
+use crate::render::shader_preprocessor::{DefineSet, PreprocessError, PreprocessedShader, ShaderPreprocessor};
 use crate::texture::{BindableTexture, TextureHandle, TextureSamplerView};
 
 pub mod mc;
@@ -66,6 +92,10 @@ pub mod texture;
 pub mod util;
 
 pub const CHUNK_STAGING_BELT_SIZE: u64 = 64_000_000;
+/// How many dirty chunk sections [WmRenderer::drive_chunk_bake_queue] hands
+/// to the rayon pool per call, so one enormous re-bake (e.g. a resource pack
+/// reload) doesn't starve the queue's prioritization for several frames.
+pub const CHUNK_BAKE_BATCH_SIZE: usize = 32;
 
 /// Provides access to most of the wgpu structs relating directly to communicating/getting
 /// information about the gpu.
@@ -87,6 +117,18 @@ pub struct WmRenderer {
     pub mc: Arc<MinecraftState>,
     pub chunk_update_queue: Arc<Mutex<Vec<(Arc<Buffer>, Vec<u8>)>>>,
     pub chunk_staging_belt: Arc<Mutex<StagingBelt>>,
+    /// Dirty chunk sections waiting to be (re)baked off the main thread,
+    /// closest to the camera first. Drive it with [Self::drive_chunk_bake_queue].
+    pub chunk_bake_queue: Arc<ChunkBakeQueue>,
+    /// Extra compute/render passes to run before the terrain/entity draw
+    /// graph; empty until a caller registers passes via [Self::pass_graph]
+    /// directly. [Self::drive_pass_graph] runs whatever's registered; it runs
+    /// only when called, once per frame, by whoever drives the frame.
+    pub pass_graph: Arc<RwLock<PassGraph>>,
+    /// Resolves `#include`/`#define`/`#ifdef` directives in shaderpack WGSL
+    /// before it's handed to [wgpu::Device::create_shader_module]. See
+    /// [Self::preprocess_shader] for the entry point a caller actually uses.
+    pub shader_preprocessor: Arc<ShaderPreprocessor>,
     #[cfg(feature = "tracing")]
     pub puffin_http: Arc<puffin_http::Server>,
 }
@@ -101,6 +143,265 @@ pub trait HasWindowSize {
     fn get_window_size(&self) -> WindowSize;
 }
 
+/// One plane of a [Frustum], in the form `dot(normal, point) + distance == 0`,
+/// normalized so [Self::signed_distance] is a true distance in world units.
+#[derive(Debug, Copy, Clone)]
+struct Plane {
+    normal: Vector3<f32>,
+    distance: f32,
+}
+
+impl Plane {
+    fn from_vec4(v: Vector4<f32>) -> Self {
+        let normal = Vector3::new(v.x, v.y, v.z);
+        let length = normal.magnitude();
+
+        Self {
+            normal: normal / length,
+            distance: v.w / length,
+        }
+    }
+
+    /// Positive when `point` is in front of the plane (inside the frustum),
+    /// negative when behind it, by how many world units.
+    fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// A view frustum, extracted from a combined model/view/projection matrix via
+/// the Gribb–Hartmann method, used to cull geometry that can't possibly be
+/// visible before it reaches the GPU.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the 6 frustum planes (left, right, bottom, top, near, far, in
+    /// that order) from `matrix`, which should be `projection * view * model`
+    /// and is indexed row-major (`matrix[row]`).
+    ///
+    /// wgpu's clip space has `z` in `0..=1` rather than OpenGL's `-1..=1`, so
+    /// unlike the classic Gribb–Hartmann derivation, the near plane is `m2`
+    /// alone instead of `m3 + m2` — row 2 is already zero at the near plane.
+    pub fn from_modelview_projection(matrix: [[f32; 4]; 4]) -> Self {
+        let m0 = Vector4::from(matrix[0]);
+        let m1 = Vector4::from(matrix[1]);
+        let m2 = Vector4::from(matrix[2]);
+        let m3 = Vector4::from(matrix[3]);
+
+        let planes = [m3 + m0, m3 - m0, m3 + m1, m3 - m1, m2, m3 - m2].map(Plane::from_vec4);
+
+        Self { planes }
+    }
+
+    /// False only when `center`/`radius` is fully outside at least one plane,
+    /// i.e. the sphere cannot possibly be visible.
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(center) >= -radius)
+    }
+
+    /// False only when every corner of the `min..=max` box falls outside the
+    /// same plane, i.e. the box cannot possibly be visible. Uses the
+    /// "positive vertex" trick: for each plane, only the box corner furthest
+    /// along the plane's normal can be in front of it, so testing that one
+    /// corner per plane is equivalent to testing all 8.
+    pub fn intersects_aabb(&self, min: Vector3<f32>, max: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let positive_vertex = Vector3::new(
+                if plane.normal.x >= 0.0 { max.x } else { min.x },
+                if plane.normal.y >= 0.0 { max.y } else { min.y },
+                if plane.normal.z >= 0.0 { max.z } else { min.z },
+            );
+
+            plane.signed_distance(positive_vertex) >= 0.0
+        })
+    }
+}
+
+/// Whether a [PassNode] reads or writes a named texture slot in
+/// [WmRenderer::texture_handles].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceAccess {
+    Read,
+    Write,
+}
+
+/// Whether a [PassNode] issues a compute or render pass when it executes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassKind {
+    Compute,
+    Render,
+}
+
+/// One node in a [PassGraph]: a named compute or render pass, plus the
+/// texture slots (keyed by name, matching [WmRenderer::texture_handles])
+/// it reads and writes. [PassGraph::resolve_order] derives a valid
+/// execution order from these declarations instead of a caller hand-
+/// ordering passes itself.
+pub struct PassNode {
+    pub name: String,
+    pub kind: PassKind,
+    reads: Vec<String>,
+    writes: Vec<String>,
+    execute: Box<dyn Fn(&WmRenderer, &mut wgpu::CommandEncoder) + Send + Sync>,
+}
+
+impl PassNode {
+    pub fn new(
+        name: impl Into<String>,
+        kind: PassKind,
+        execute: impl Fn(&WmRenderer, &mut wgpu::CommandEncoder) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            execute: Box::new(execute),
+        }
+    }
+
+    /// Declares that this pass samples/binds `texture_handle` before doing
+    /// anything else, so [PassGraph::resolve_order] schedules it after
+    /// whichever registered pass last wrote that slot.
+    pub fn reads(mut self, texture_handle: impl Into<String>) -> Self {
+        self.reads.push(texture_handle.into());
+        self
+    }
+
+    /// Declares that this pass renders or writes into `texture_handle`.
+    /// [WmRenderer::execute_pass_graph] allocates the slot via
+    /// [WmRenderer::create_texture_handle] first if it doesn't exist yet.
+    pub fn writes(mut self, texture_handle: impl Into<String>) -> Self {
+        self.writes.push(texture_handle.into());
+        self
+    }
+
+    /// Declares a slot this pass touches with the given [ResourceAccess],
+    /// equivalent to calling [Self::reads] or [Self::writes] directly but
+    /// useful when the direction is only known at runtime (e.g. built from
+    /// a shaderpack's own pass descriptor).
+    pub fn slot(self, texture_handle: impl Into<String>, access: ResourceAccess) -> Self {
+        match access {
+            ResourceAccess::Read => self.reads(texture_handle),
+            ResourceAccess::Write => self.writes(texture_handle),
+        }
+    }
+}
+
+/// Why a [PassGraph] couldn't be ordered.
+#[derive(Debug)]
+pub enum PassGraphError {
+    /// The named passes' declared reads/writes form a cycle and have no
+    /// valid execution order.
+    Cycle(Vec<String>),
+}
+
+/// A set of [PassNode]s ordered by their declared resource dependencies,
+/// so compute and render passes can be composed declaratively (e.g. a
+/// compute pass feeding [WmRenderer::upload_animated_block_buffer], a
+/// GPU-driven culling pass, or a post-processing chain) instead of being
+/// hard-wired into [WmRenderer::render].
+///
+/// This only orders and dispatches passes registered through
+/// [Self::add_pass]; the existing terrain/entity draw graph
+/// ([crate::render::graph::ShaderGraph]) still runs as its own fixed step
+/// via [WmRenderer::render] — giving `ShaderGraph`'s internal passes the
+/// same texture-slot declarations would mean restructuring that module,
+/// which lives outside what this change can reach.
+#[derive(Default)]
+pub struct PassGraph {
+    nodes: Vec<PassNode>,
+}
+
+impl PassGraph {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    pub fn add_pass(&mut self, pass: PassNode) -> &mut Self {
+        self.nodes.push(pass);
+        self
+    }
+
+    /// Topologically sorts passes so each one runs after the last-registered
+    /// pass that writes each slot it reads. At every step the
+    /// earliest-registered pass whose dependencies have all run goes next.
+    fn resolve_order(&self) -> Result<Vec<usize>, PassGraphError> {
+        let mut last_writer: HashMap<&str, usize> = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            for slot in &node.writes {
+                last_writer.insert(slot.as_str(), index);
+            }
+        }
+
+        let mut depends_on: Vec<HashSet<usize>> = vec![HashSet::new(); self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            for slot in &node.reads {
+                if let Some(&writer) = last_writer.get(slot.as_str()) {
+                    if writer != index {
+                        depends_on[index].insert(writer);
+                    }
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut placed = vec![false; self.nodes.len()];
+
+        while order.len() < self.nodes.len() {
+            let next = (0..self.nodes.len())
+                .find(|&i| !placed[i] && depends_on[i].iter().all(|dep| placed[*dep]));
+
+            match next {
+                Some(index) => {
+                    placed[index] = true;
+                    order.push(index);
+                }
+                None => {
+                    let remaining = (0..self.nodes.len())
+                        .filter(|&i| !placed[i])
+                        .map(|i| self.nodes[i].name.clone())
+                        .collect();
+                    return Err(PassGraphError::Cycle(remaining));
+                }
+            }
+        }
+
+        Ok(order)
+    }
+}
+
+/// Which GPU texture layout block faces sample from, selected once at
+/// [WmRenderer::init] time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlockTextureBackend {
+    /// Every block texture is packed into shared UV space in one
+    /// [render::atlas::Atlas], addressed via [mc::block::BlockMeshVertex::tex_coords].
+    /// The default, and currently the only backend [WmRenderer::init]
+    /// actually builds.
+    #[default]
+    Atlas,
+    /// Each block texture would occupy its own layer of a
+    /// `texture_2d_array`, addressed per-vertex by
+    /// [mc::block::BlockMeshVertex::tex_index] instead of atlas-offset UVs
+    /// - removing atlas-packing bleed and letting resource packs load
+    /// textures larger than one atlas could hold.
+    ///
+    /// Selecting this still builds the [Self::Atlas] backend underneath:
+    /// the layered-texture allocator itself belongs in [render::atlas],
+    /// which this change can't reach, so there's nothing yet on the GPU
+    /// side to build layers into. What this change can reach, meshing
+    /// (`mc::block`), does differ: selecting this backend makes
+    /// [mc::block::BlockMeshVertex::tex_index] a real per-texture layer
+    /// index (see `mc::block::texture_layer_index`) instead of staying `0`.
+    TextureArray,
+}
+
 impl WmRenderer {
 
     pub fn new(wgpu_state: WgpuState, resource_provider: Arc<dyn ResourceProvider>) -> WmRenderer {
@@ -115,6 +416,8 @@ impl WmRenderer {
 
         let pipelines = WmPipelines::new(resource_provider.clone());
 
+        crate::mc::tint::load_startup(&*resource_provider);
+
         let mc = MinecraftState::new(&wgpu_state, resource_provider);
 
         Self {
@@ -125,15 +428,54 @@ impl WmRenderer {
             mc: Arc::new(mc),
             chunk_update_queue: Arc::new(Mutex::new(Vec::new())),
             chunk_staging_belt: Arc::new(Mutex::new(StagingBelt::new(CHUNK_STAGING_BELT_SIZE))),
+            chunk_bake_queue: Arc::new(ChunkBakeQueue::new(
+                std::thread::available_parallelism()
+                    .map(|n| n.get().saturating_sub(1).max(1))
+                    .unwrap_or(4),
+                CHUNK_BAKE_BATCH_SIZE,
+            )),
+            pass_graph: Arc::new(RwLock::new(PassGraph::new())),
+            shader_preprocessor: Arc::new(ShaderPreprocessor::new()),
             #[cfg(feature = "tracing")]
             puffin_http,
         }
     }
 
-    pub fn init(&self) {
+    /// Resolves `path` through [Self::shader_preprocessor] against
+    /// [Self::mc]'s resource provider, expanding `#include`s and evaluating
+    /// `#ifdef`s under `defines`. Call this instead of reading shaderpack
+    /// WGSL directly, so shared includes (lighting, fog, noise) and feature
+    /// conditionals are resolved before the source reaches
+    /// [wgpu::Device::create_shader_module].
+    pub fn preprocess_shader(
+        &self,
+        path: &crate::mc::resource::ResourcePath,
+        defines: &DefineSet,
+    ) -> Result<Arc<PreprocessedShader>, PreprocessError> {
+        self.shader_preprocessor
+            .resolve(path, defines, &*self.mc.resource_provider)
+    }
+
+    /// `block_texture_backend` picks which GPU texture layout block faces
+    /// sample from; see [BlockTextureBackend]'s docs for what's actually
+    /// implemented today.
+    pub fn init(&self, block_texture_backend: BlockTextureBackend) {
         let pipelines = self.pipelines.load();
         pipelines.init(self);
 
+        crate::mc::block::set_texture_array_backend_active(matches!(
+            block_texture_backend,
+            BlockTextureBackend::TextureArray
+        ));
+
+        match block_texture_backend {
+            //The GPU-side layered texture allocator still has nothing to
+            //build into (see TextureArray's docs), so both variants build
+            //the same Atlas underneath; only meshing's per-vertex tex_index
+            //(via mc::block::set_texture_array_backend_active) differs.
+            BlockTextureBackend::Atlas | BlockTextureBackend::TextureArray => {}
+        }
+
         let atlases = [BLOCK_ATLAS, ENTITY_ATLAS]
             .iter()
             .map(|&name| {
@@ -265,6 +607,56 @@ impl WmRenderer {
         );
     }
 
+    /// Runs every [PassNode] in `pass_graph` in dependency order on a
+    /// single command encoder, allocating any texture slot a pass writes
+    /// (sized to `surface_config`) if it isn't already in
+    /// [Self::texture_handles]. Call this before [Self::render] so passes
+    /// that feed the fixed terrain/entity draw graph (e.g. a compute pass
+    /// writing a buffer [Self::upload_animated_block_buffer] will read, or
+    /// a post-process target) have already landed by the time it runs.
+    pub fn execute_pass_graph(
+        &self,
+        pass_graph: &PassGraph,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Result<(), PassGraphError> {
+        let order = pass_graph.resolve_order()?;
+
+        for &index in &order {
+            for slot in &pass_graph.nodes[index].writes {
+                if !self.texture_handles.read().contains_key(slot.as_str()) {
+                    self.create_texture_handle(slot.clone(), surface_config.format, surface_config);
+                }
+            }
+        }
+
+        let mut encoder = self
+            .wgpu_state
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        for &index in &order {
+            (pass_graph.nodes[index].execute)(self, &mut encoder);
+        }
+
+        self.wgpu_state.queue.submit([encoder.finish()]);
+
+        Ok(())
+    }
+
+    /// Runs [Self::pass_graph] via [Self::execute_pass_graph]. [Self::render]
+    /// does not call this; whoever drives the frame calls it once per frame,
+    /// before handing `output_texture_view` to the draw graph, so
+    /// anything registered in [Self::pass_graph] (a compute pass feeding
+    /// [Self::upload_animated_block_buffer], a shadow pre-pass, GPU-driven
+    /// culling) has already landed. No tracked caller registers a pass yet,
+    /// so today this runs an empty graph and is a no-op.
+    pub fn drive_pass_graph(
+        &self,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Result<(), PassGraphError> {
+        self.execute_pass_graph(&self.pass_graph.read(), surface_config)
+    }
+
     pub fn render<'graph: 'resources, 'resources>(
         &self,
         graph: &ShaderGraph,
@@ -316,6 +708,61 @@ impl WmRenderer {
         staging_belt.recall();
     }
 
+    /// Kicks off the next batch on [Self::chunk_bake_queue] and uploads
+    /// whatever finished baking since the last call into
+    /// [crate::mc::chunk::ChunkAllocation]. Call this once per frame from
+    /// the same thread that drives [Self::submit_chunk_updates], so all GPU
+    /// writes stay on one thread even though the baking itself happened on
+    /// the rayon pool.
+    pub fn drive_chunk_bake_queue(&self) {
+        puffin::profile_function!();
+
+        self.chunk_bake_queue.dispatch_batch();
+
+        for result in self.chunk_bake_queue.poll_finished() {
+            let loaded_chunks = self.mc.chunks.loaded_chunks.read();
+            let chunk = match loaded_chunks.get(&result.pos) {
+                Some(chunk) => chunk.load(),
+                //The chunk was unloaded while its bake was in flight.
+                None => continue,
+            };
+
+            //This chunk's previous bake (if any) is about to be replaced;
+            //free its old ranges first so re-bakes don't leak space.
+            free_layer_ranges(
+                &self.mc.chunks.chunk_allocation,
+                chunk.baked_layers.read().values(),
+            );
+
+            let baked_layers = result
+                .layers
+                .into_iter()
+                .map(|(name, bytes)| {
+                    let range = {
+                        let mut allocator = self.mc.chunks.chunk_allocation.allocator.write();
+                        let range = allocator.allocate_range(bytes.len()).unwrap();
+                        self.mc
+                            .chunks
+                            .chunk_allocation
+                            .used_bytes
+                            .fetch_add(bytes.len(), std::sync::atomic::Ordering::AcqRel);
+                        range
+                    };
+
+                    self.wgpu_state.queue.write_buffer(
+                        &self.mc.chunks.chunk_allocation.buffer,
+                        range.start as u64,
+                        &bytes,
+                    );
+
+                    (name, range)
+                })
+                .collect();
+
+            *chunk.baked_layers.write() = baked_layers;
+        }
+    }
+
     pub fn get_backend_description(&self) -> String {
         format!(
             "wgpu 0.18 ({:?})",
@@ -1,10 +1,15 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, OnceLock};
+
 use bytemuck::{Pod, Zeroable};
-use cgmath::{Matrix4, SquareMatrix, Vector4};
+use cgmath::{Angle, Deg, Matrix2, Matrix4, SquareMatrix, Vector2, Vector3, Vector4};
 use itertools::Itertools;
 use minecraft_assets::api::ModelResolver;
 use minecraft_assets::schemas;
 use minecraft_assets::schemas::blockstates::ModelProperties;
 use minecraft_assets::schemas::models::Textures;
+use parking_lot::RwLock;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::mc::resource::ResourceProvider;
@@ -66,6 +71,12 @@ pub struct BlockMeshVertex {
     pub tex_coords: [u16; 2],
     pub normal: [f32; 3],
     pub animation_uv_offset: u32,
+    /// Which layer of a block texture array this vertex samples, for the
+    /// [crate::BlockTextureBackend::TextureArray] path; see
+    /// [texture_layer_index]. Always `0` under the atlas-offset-UV path
+    /// `tex_coords` already supports, since [crate::BlockTextureBackend::Atlas]
+    /// never activates the layer assignment.
+    pub tex_index: u32,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -145,33 +156,48 @@ fn resolve_model(
     schema
 }
 
+/// Whether [crate::BlockTextureBackend::TextureArray] was selected at init
+/// time, set once via [set_texture_array_backend_active]. Lives as a module
+/// static rather than a field on [BlockManager]/[crate::mc::MinecraftState]
+/// because meshing (this module) doesn't otherwise have a handle to either.
+static TEXTURE_ARRAY_BACKEND_ACTIVE: AtomicU8 = AtomicU8::new(0);
+
+/// Every block texture's stable layer index under
+/// [crate::BlockTextureBackend::TextureArray], assigned the first time
+/// [texture_layer_index] sees that texture. Same module-static reasoning as
+/// [TEXTURE_ARRAY_BACKEND_ACTIVE].
+static TEXTURE_LAYER_INDICES: OnceLock<RwLock<HashMap<ResourcePath, u32>>> = OnceLock::new();
+
+/// Called once from [crate::WmRenderer::init] with the selected
+/// [crate::BlockTextureBackend], so [texture_layer_index] knows whether to
+/// actually assign layers or keep returning `0`.
+pub fn set_texture_array_backend_active(active: bool) {
+    TEXTURE_ARRAY_BACKEND_ACTIVE.store(active as u8, Ordering::Relaxed);
+}
+
+/// `texture_path`'s stable array layer index, assigning the next free index
+/// the first time it's seen. Returns `0` without assigning anything when
+/// [crate::BlockTextureBackend::TextureArray] isn't active, matching
+/// [BlockMeshVertex::tex_index]'s meaning under the atlas path.
+fn texture_layer_index(texture_path: &ResourcePath) -> u32 {
+    if TEXTURE_ARRAY_BACKEND_ACTIVE.load(Ordering::Relaxed) == 0 {
+        return 0;
+    }
+
+    let indices = TEXTURE_LAYER_INDICES.get_or_init(|| RwLock::new(HashMap::new()));
+
+    if let Some(&index) = indices.read().get(texture_path) {
+        return index;
+    }
+
+    let mut indices = indices.write();
+    let next_index = indices.len() as u32;
+    *indices.entry(texture_path.clone()).or_insert(next_index)
+}
+
 fn get_atlas_uv(face: &schemas::models::ElementFace, block_atlas: &Atlas, textures: &Option<Textures>) -> Option<UV> {
     let atlas_map = block_atlas.uv_map.read();
 
-    // atlas_map.get(&(&face.texture.0).into()).copied().map(|uv| {
-    //     let u = Vector2::new(uv.0.0 as i32, uv.0.1 as i32);
-    //     let v = Vector2::new(uv.1.0 as i32, uv.1.1 as i32);
-    //
-    //     let d = v - u;
-    //     let center = u + (d / 2);
-    //
-    //     let u_shift = u - center;
-    //     let v_shift = v - center;
-    //
-    //     let matrix = match face.rotation {
-    //         0 => Matrix2::new(1.0, 0.0, 0.0, 1.0),
-    //         90 => Matrix2::new(0.0, 1.0, -1.0, 0.0),
-    //         180 => Matrix2::new(-1.0, 0.0, 0.0, -1.0),
-    //         270 => Matrix2::new(0.0, -1.0, 1.0, 0.0),
-    //         _ => unreachable!()
-    //     };
-    //
-    //     let u = matrix * u_shift.cast::<f32>().unwrap();
-    //     let v = matrix * v_shift.cast::<f32>().unwrap();
-    //
-    //     ((u.x as u16 + center.x as u16, u.y as u16 + center.y as u16), (v.x as u16 + center.x as u16, v.y as u16 + center.y as u16))
-    // })
-
     let texture_path: ResourcePath = match face.texture.reference() {
         None => {
             face.texture.0.clone().into()
@@ -183,7 +209,77 @@ fn get_atlas_uv(face: &schemas::models::ElementFace, block_atlas: &Atlas, textur
         }
     };
 
-    atlas_map.get(&texture_path).copied()
+    let uv = atlas_map.get(&texture_path).copied()?;
+
+    Some(rotate_uv_quad(uv, face.rotation))
+}
+
+/// Rotates `uv`'s rectangle about its own center by `rotation` degrees
+/// (0/90/180/270), before the caller crops it with the face's `tex.uv`.
+fn rotate_uv_quad(uv: UV, rotation: u32) -> UV {
+    let u = Vector2::new(uv.0.0 as i32, uv.0.1 as i32);
+    let v = Vector2::new(uv.1.0 as i32, uv.1.1 as i32);
+
+    let d = v - u;
+    let center = u + (d / 2);
+
+    let u_shift = (u - center).cast::<f32>().unwrap();
+    let v_shift = (v - center).cast::<f32>().unwrap();
+
+    let matrix = match rotation {
+        0 => Matrix2::new(1.0, 0.0, 0.0, 1.0),
+        90 => Matrix2::new(0.0, 1.0, -1.0, 0.0),
+        180 => Matrix2::new(-1.0, 0.0, 0.0, -1.0),
+        270 => Matrix2::new(0.0, -1.0, 1.0, 0.0),
+        _ => unreachable!()
+    };
+
+    let u = matrix * u_shift;
+    let v = matrix * v_shift;
+
+    //Offsets are added to the center as i32 before narrowing: a negative
+    //f32 offset cast straight to u16 would saturate to 0.
+    (((center.x + u.x as i32) as u16, (center.y + u.y as i32) as u16), ((center.x + v.x as i32) as u16, (center.y + v.y as i32) as u16))
+}
+
+/// Builds the model-space transform for an element's `rotation` block: translate
+/// `origin` to the world origin, rotate by `angle` about `axis`, optionally
+/// rescale the two axes perpendicular to `axis` by `1 / cos(angle)`, then
+/// translate back.
+fn element_rotation_matrix(rotation: &schemas::models::ElementRotation) -> Matrix4<f32> {
+    let origin = Vector3::new(
+        1.0 - rotation.origin[0] / 16.0,
+        rotation.origin[1] / 16.0,
+        rotation.origin[2] / 16.0,
+    );
+
+    //The vertex transform above mirrors the x axis (`1.0 - x / 16.0`), so a
+    //rotation about any axis other than X needs its angle negated to rotate
+    //in the same direction the model author intended.
+    let angle = Deg(rotation.angle);
+    let angle = match rotation.axis {
+        schemas::models::Axis::X => angle,
+        _ => -angle,
+    };
+
+    let mut matrix = match rotation.axis {
+        schemas::models::Axis::X => Matrix4::from_angle_x(angle),
+        schemas::models::Axis::Y => Matrix4::from_angle_y(angle),
+        schemas::models::Axis::Z => Matrix4::from_angle_z(angle),
+    };
+
+    if rotation.rescale {
+        let scale = 1.0 / angle.cos();
+
+        matrix = matrix
+            * match rotation.axis {
+                schemas::models::Axis::X => Matrix4::from_nonuniform_scale(1.0, scale, scale),
+                schemas::models::Axis::Y => Matrix4::from_nonuniform_scale(scale, 1.0, scale),
+                schemas::models::Axis::Z => Matrix4::from_nonuniform_scale(scale, scale, 1.0),
+            };
+    }
+
+    Matrix4::from_translation(origin) * matrix * Matrix4::from_translation(-origin)
 }
 
 pub struct RenderSettings {
@@ -263,9 +359,6 @@ impl ModelMesh {
                     }
                 };
 
-                // let matrix = Matrix4::from_angle_y(Deg(45.0));
-                let matrix = Matrix4::identity();
-
                 let results = model
                     .elements
                     .iter()
@@ -273,6 +366,12 @@ impl ModelMesh {
                     .map(|element| {
                         //Face textures
 
+                        let matrix = element
+                            .rotation
+                            .as_ref()
+                            .map(element_rotation_matrix)
+                            .unwrap_or_else(Matrix4::identity);
+
                         let tex_map = |&tex| {
                             get_atlas_uv(
                                 tex,
@@ -289,7 +388,8 @@ impl ModelMesh {
                                     *block_atlas.animated_texture_offsets.read()
                                         .get(&(&tex.texture.0).into())
                                         .unwrap_or(&0),
-                                    tex.tint_index
+                                    tex.tint_index,
+                                    texture_layer_index(&(&tex.texture.0).into()),
                                 )
                             })
                         };
@@ -317,7 +417,7 @@ impl ModelMesh {
                         let g = (matrix * Vector4::new(1.0 - element.to[0] / 16.0, element.to[1] / 16.0, element.to[2] / 16.0, 1.0)).truncate().into();
                         let h = (matrix * Vector4::new(1.0 - element.from[0] / 16.0, element.to[1] / 16.0, element.to[2] / 16.0, 1.0)).truncate().into();
 
-                        const NO_UV: (UV, u32, i32) = (((0, 0), (0, 0)), 0, -1);
+                        const NO_UV: (UV, u32, i32, u32) = (((0, 0), (0, 0)), 0, -1, 0);
 
                         //It's valid behavior for a face to not be defined in a block model. If that happens it won't be included
                         //in the chunk indices when rendering, but we need some placeholder, so we zero it out, which is fine because
@@ -343,42 +443,42 @@ impl ModelMesh {
                         #[rustfmt::skip]
                         let faces = BlockModelFaces {
                             vertices: [
-                                BlockMeshVertex { position: h, tex_coords: [south_face.0.1.0, south_face.0.0.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1 },
-                                BlockMeshVertex { position: g, tex_coords: [south_face.0.0.0, south_face.0.0.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1 },
-                                BlockMeshVertex { position: f, tex_coords: [south_face.0.0.0, south_face.0.1.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1 },
-                                BlockMeshVertex { position: e, tex_coords: [south_face.0.1.0, south_face.0.1.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1 },
-
-                                BlockMeshVertex { position: f, tex_coords: [west_face.0.1.0, west_face.0.1.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1 },
-                                BlockMeshVertex { position: g, tex_coords: [west_face.0.1.0, west_face.0.0.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1 },
-                                BlockMeshVertex { position: c, tex_coords: [west_face.0.0.0, west_face.0.0.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1 },
-                                BlockMeshVertex { position: b, tex_coords: [west_face.0.0.0, west_face.0.1.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1 },
-
-                                BlockMeshVertex { position: a, tex_coords: [north_face.0.0.0, north_face.0.1.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1 },
-                                BlockMeshVertex { position: b, tex_coords: [north_face.0.1.0, north_face.0.1.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1 },
-                                BlockMeshVertex { position: c, tex_coords: [north_face.0.1.0, north_face.0.0.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1 },
-                                BlockMeshVertex { position: d, tex_coords: [north_face.0.0.0, north_face.0.0.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1 },
-
-                                BlockMeshVertex { position: h, tex_coords: [east_face.0.0.0, east_face.0.0.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1 },
-                                BlockMeshVertex { position: e, tex_coords: [east_face.0.0.0, east_face.0.1.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1 },
-                                BlockMeshVertex { position: a, tex_coords: [east_face.0.1.0, east_face.0.1.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1 },
-                                BlockMeshVertex { position: d, tex_coords: [east_face.0.1.0, east_face.0.0.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1 },
-
-                                BlockMeshVertex { position: d, tex_coords: [up_face.0.0.0, up_face.0.1.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1 },
-                                BlockMeshVertex { position: c, tex_coords: [up_face.0.1.0, up_face.0.1.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1 },
-                                BlockMeshVertex { position: g, tex_coords: [up_face.0.1.0, up_face.0.0.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1 },
-                                BlockMeshVertex { position: h, tex_coords: [up_face.0.0.0, up_face.0.0.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1 },
-
-                                BlockMeshVertex { position: e, tex_coords: [down_face.0.1.0, down_face.0.1.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1 },
-                                BlockMeshVertex { position: f, tex_coords: [down_face.0.0.0, down_face.0.1.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1 },
-                                BlockMeshVertex { position: b, tex_coords: [down_face.0.0.0, down_face.0.0.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1 },
-                                BlockMeshVertex { position: a, tex_coords: [down_face.0.1.0, down_face.0.0.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1 },
+                                BlockMeshVertex { position: h, tex_coords: [south_face.0.1.0, south_face.0.0.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1, tex_index: south_face.3 },
+                                BlockMeshVertex { position: g, tex_coords: [south_face.0.0.0, south_face.0.0.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1, tex_index: south_face.3 },
+                                BlockMeshVertex { position: f, tex_coords: [south_face.0.0.0, south_face.0.1.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1, tex_index: south_face.3 },
+                                BlockMeshVertex { position: e, tex_coords: [south_face.0.1.0, south_face.0.1.1], normal: [0.0, 0.0, 1.0], animation_uv_offset: south_face.1, tex_index: south_face.3 },
+
+                                BlockMeshVertex { position: f, tex_coords: [west_face.0.1.0, west_face.0.1.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1, tex_index: west_face.3 },
+                                BlockMeshVertex { position: g, tex_coords: [west_face.0.1.0, west_face.0.0.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1, tex_index: west_face.3 },
+                                BlockMeshVertex { position: c, tex_coords: [west_face.0.0.0, west_face.0.0.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1, tex_index: west_face.3 },
+                                BlockMeshVertex { position: b, tex_coords: [west_face.0.0.0, west_face.0.1.1], normal: [-1.0, 0.0, 0.0], animation_uv_offset: west_face.1, tex_index: west_face.3 },
+
+                                BlockMeshVertex { position: a, tex_coords: [north_face.0.0.0, north_face.0.1.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1, tex_index: north_face.3 },
+                                BlockMeshVertex { position: b, tex_coords: [north_face.0.1.0, north_face.0.1.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1, tex_index: north_face.3 },
+                                BlockMeshVertex { position: c, tex_coords: [north_face.0.1.0, north_face.0.0.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1, tex_index: north_face.3 },
+                                BlockMeshVertex { position: d, tex_coords: [north_face.0.0.0, north_face.0.0.1], normal: [0.0, 0.0, -1.0], animation_uv_offset: north_face.1, tex_index: north_face.3 },
+
+                                BlockMeshVertex { position: h, tex_coords: [east_face.0.0.0, east_face.0.0.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1, tex_index: east_face.3 },
+                                BlockMeshVertex { position: e, tex_coords: [east_face.0.0.0, east_face.0.1.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1, tex_index: east_face.3 },
+                                BlockMeshVertex { position: a, tex_coords: [east_face.0.1.0, east_face.0.1.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1, tex_index: east_face.3 },
+                                BlockMeshVertex { position: d, tex_coords: [east_face.0.1.0, east_face.0.0.1], normal: [1.0, 0.0, 0.0], animation_uv_offset: east_face.1, tex_index: east_face.3 },
+
+                                BlockMeshVertex { position: d, tex_coords: [up_face.0.0.0, up_face.0.1.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1, tex_index: up_face.3 },
+                                BlockMeshVertex { position: c, tex_coords: [up_face.0.1.0, up_face.0.1.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1, tex_index: up_face.3 },
+                                BlockMeshVertex { position: g, tex_coords: [up_face.0.1.0, up_face.0.0.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1, tex_index: up_face.3 },
+                                BlockMeshVertex { position: h, tex_coords: [up_face.0.0.0, up_face.0.0.1], normal: [0.0, 1.0, 0.0], animation_uv_offset: up_face.1, tex_index: up_face.3 },
+
+                                BlockMeshVertex { position: e, tex_coords: [down_face.0.1.0, down_face.0.1.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1, tex_index: down_face.3 },
+                                BlockMeshVertex { position: f, tex_coords: [down_face.0.0.0, down_face.0.1.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1, tex_index: down_face.3 },
+                                BlockMeshVertex { position: b, tex_coords: [down_face.0.0.0, down_face.0.0.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1, tex_index: down_face.3 },
+                                BlockMeshVertex { position: a, tex_coords: [down_face.0.1.0, down_face.0.0.1], normal: [0.0, -1.0, 0.0], animation_uv_offset: down_face.1, tex_index: down_face.3 },
                             ],
-                            south: south.map(|(_, _, tint_index)| Face { vert_index: 0, tint_index}),
-                            west: west.map(|(_, _, tint_index)| Face { vert_index: 4, tint_index}),
-                            north: north.map(|(_, _, tint_index)| Face { vert_index: 8, tint_index}),
-                            east: east.map(|(_, _, tint_index)| Face { vert_index: 12, tint_index}),
-                            up: up.map(|(_, _, tint_index)| Face { vert_index: 16, tint_index}),
-                            down: down.map(|(_, _, tint_index)| Face { vert_index: 20, tint_index}),
+                            south: south.map(|(_, _, tint_index, _)| Face { vert_index: 0, tint_index}),
+                            west: west.map(|(_, _, tint_index, _)| Face { vert_index: 4, tint_index}),
+                            north: north.map(|(_, _, tint_index, _)| Face { vert_index: 8, tint_index}),
+                            east: east.map(|(_, _, tint_index, _)| Face { vert_index: 12, tint_index}),
+                            up: up.map(|(_, _, tint_index, _)| Face { vert_index: 16, tint_index}),
+                            down: down.map(|(_, _, tint_index, _)| Face { vert_index: 20, tint_index}),
                             cube: current_element_is_full_cube,
                         };
 
@@ -398,3 +498,329 @@ impl ModelMesh {
         })
     }
 }
+
+/// One of a blockstate's weighted model variants (the `variants` section of
+/// the blockstate JSON, as opposed to `multipart`). Every candidate is baked
+/// once up front and one is picked per-position at chunk-bake time.
+#[derive(Debug)]
+struct WeightedVariant {
+    mesh: Arc<ModelMesh>,
+    weight: u32,
+}
+
+/// The full set of weighted variants a blockstate resolved to, keyed by the
+/// formatted property state it was registered under (see [format_state_key]),
+/// plus the precomputed weight total so a selection roll is only taken mod
+/// it once.
+#[derive(Debug)]
+struct VariantGroup {
+    key: String,
+    variants: Vec<WeightedVariant>,
+    total_weight: u32,
+}
+
+impl VariantGroup {
+    fn new(key: String, variants: Vec<WeightedVariant>) -> Self {
+        let total_weight = variants.iter().map(|v| v.weight).sum::<u32>().max(1);
+        Self {
+            key,
+            variants,
+            total_weight,
+        }
+    }
+
+    /// Picks a variant deterministically from `(x, y, z)`: the same position
+    /// always selects the same variant, but neighbors are very likely to
+    /// differ, which is what breaks up the tiling vanilla avoids the same way.
+    fn select(&self, x: i32, y: i16, z: i32) -> Arc<ModelMesh> {
+        if self.variants.len() == 1 {
+            return self.variants[0].mesh.clone();
+        }
+
+        let roll = position_hash(x, y, z) % self.total_weight as u64;
+
+        let mut accumulated = 0u32;
+        for variant in &self.variants {
+            accumulated += variant.weight;
+            if roll < accumulated as u64 {
+                return variant.mesh.clone();
+            }
+        }
+
+        //Only reached when every weight is 0 (total_weight is clamped to 1,
+        //so the roll is 0 and no variant's accumulated weight exceeds it);
+        //the last variant is as good a fallback as any.
+        self.variants.last().unwrap().mesh.clone()
+    }
+}
+
+/// A stable (non-cryptographic) hash of a block position, used to seed
+/// per-position variant selection. MurmurHash3's 64-bit finalizer (fmix64),
+/// seeded from the coordinates mixed together.
+fn position_hash(x: i32, y: i16, z: i32) -> u64 {
+    let mut h = (x as i64 as u64)
+        .wrapping_mul(0x9E3779B97F4A7C15)
+        ^ (y as i64 as u64).wrapping_mul(0xC2B2AE3D27D4EB4F)
+        ^ (z as i64 as u64).wrapping_mul(0x165667B19E3779F9);
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51AFD7ED558CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CEB9FE1A85EC53);
+    h ^= h >> 33;
+    h
+}
+
+/// Formats a blockstate's property values into a stable, order-independent
+/// key, used to look up the [VariantGroup] registered for that combination.
+/// Each value is written with its `Debug` impl, so `StateValue`s come out as
+/// e.g. `facing=String("north"),lit=Bool(true)`.
+fn format_state_key<'a>(
+    state: impl IntoIterator<Item = (&'a str, &'a dyn std::fmt::Debug)>,
+) -> String {
+    let mut pairs = state
+        .into_iter()
+        .map(|(name, value)| format!("{name}={value:?}"))
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs.join(",")
+}
+
+/// A registered block: its name, and every blockstate it can resolve to,
+/// cached as baked [ModelMesh]es keyed by the `augment` half of a
+/// [BlockstateKey] (see [BlockManager](crate::mc::BlockManager)). Lookups
+/// ([Self::get_model_by_key], [Self::get_or_bake_model]) go through
+/// `variant_index`, an O(1) map from formatted state to `augment`, instead
+/// of rescanning every registered [VariantGroup].
+#[derive(Debug)]
+pub struct Block {
+    pub name: String,
+    variants: RwLock<Vec<VariantGroup>>,
+    /// `variants`' formatted state keys, for an O(1) lookup instead of
+    /// scanning every registered [VariantGroup] on each call.
+    variant_index: RwLock<HashMap<String, u16>>,
+}
+
+impl Block {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            variants: RwLock::new(Vec::new()),
+            variant_index: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Looks up the [VariantGroup] already registered (via
+    /// [Self::register_variants]) for `state`'s formatted property values
+    /// (see [format_state_key]), and returns one of its weighted candidates along
+    /// with the `augment` future [Self::get_model] calls should use.
+    /// `position_seed` stands in for a real block position for non-chunk
+    /// callers (tools, the demo); the chunk baker instead goes through
+    /// [Self::get_model] with the block's actual position.
+    pub fn get_model_by_key<'a>(
+        &self,
+        state: impl IntoIterator<Item = (&'a str, &'a dyn std::fmt::Debug)>,
+        _resource_provider: &dyn ResourceProvider,
+        _block_atlas: &Atlas,
+        position_seed: i64,
+    ) -> Result<(Arc<ModelMesh>, u16), MeshBakeError> {
+        let key = format_state_key(state);
+
+        let augment = *self
+            .variant_index
+            .read()
+            .get(&key)
+            .ok_or_else(|| MeshBakeError::UnresolvedResourcePath(ResourcePath::from(&self.name)))?;
+
+        Ok((
+            self.variants.read()[augment as usize].select(position_seed as i32, 0, 0),
+            augment,
+        ))
+    }
+
+    /// Like [Self::get_model_by_key], but bakes and registers `candidates`
+    /// itself the first time `state` is seen (or the first time since
+    /// [Self::invalidate_models] last ran), instead of requiring a separate
+    /// up-front [Self::register_variants] call. Every later lookup for the
+    /// same state is then the same `variant_index` hit [Self::get_model_by_key]
+    /// uses, not a re-bake.
+    pub fn get_or_bake_model<'a>(
+        &self,
+        state: impl IntoIterator<Item = (&'a str, &'a dyn std::fmt::Debug)>,
+        candidates: &[ModelProperties],
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+        position_seed: i64,
+    ) -> Result<(Arc<ModelMesh>, u16), MeshBakeError> {
+        let key = format_state_key(state);
+
+        if let Some(&augment) = self.variant_index.read().get(&key) {
+            return Ok((
+                self.variants.read()[augment as usize].select(position_seed as i32, 0, 0),
+                augment,
+            ));
+        }
+
+        let group = bake_variant_group(candidates, resource_provider, block_atlas)?;
+
+        let mut variants = self.variants.write();
+        let mut variant_index = self.variant_index.write();
+
+        //Baking above happens without holding either lock, so another
+        //caller may have already registered this exact state in the meantime.
+        if let Some(&augment) = variant_index.get(&key) {
+            return Ok((variants[augment as usize].select(position_seed as i32, 0, 0), augment));
+        }
+
+        variants.push(VariantGroup::new(key.clone(), group));
+        let augment = (variants.len() - 1) as u16;
+        variant_index.insert(key, augment);
+
+        Ok((variants[augment as usize].select(position_seed as i32, 0, 0), augment))
+    }
+
+    /// Registers the weighted candidates `state` resolves to, baking each one
+    /// up front and returning the `augment` key future lookups should use.
+    pub fn register_variants(
+        &self,
+        state: impl IntoIterator<Item = (&'static str, &'static dyn std::fmt::Debug)>,
+        candidates: &[ModelProperties],
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+    ) -> Result<u16, MeshBakeError> {
+        let baked = bake_variant_group(candidates, resource_provider, block_atlas)?;
+
+        let key = format_state_key(state);
+        let mut variants = self.variants.write();
+        variants.push(VariantGroup::new(key.clone(), baked));
+        let augment = (variants.len() - 1) as u16;
+        self.variant_index.write().insert(key, augment);
+        Ok(augment)
+    }
+
+    /// Fetches the already-baked mesh for `augment`, selecting among its
+    /// weighted variants (if more than one) using `(x, y, z)`.
+    pub fn get_model(&self, augment: u16, x: i32, y: i16, z: i32) -> Arc<ModelMesh> {
+        self.variants.read()[augment as usize].select(x, y, z)
+    }
+
+    /// Drops every baked variant, forgetting the `augment` indices they were
+    /// registered under. Call this on every block in the
+    /// [BlockManager](crate::mc::BlockManager) when the resource pack or
+    /// block atlas changes, so the next [Self::get_or_bake_model] call
+    /// re-bakes against the new textures/models instead of serving a stale
+    /// `Arc<ModelMesh>`; [Self::get_model_by_key] has no re-bake path of its
+    /// own and will error until something registers variants again.
+    pub fn invalidate_models(&self) {
+        self.variants.write().clear();
+        self.variant_index.write().clear();
+    }
+}
+
+/// Bakes `candidates` into a [VariantGroup]'s weighted variants; shared by
+/// [Block::register_variants] and [Block::get_or_bake_model] so there's one
+/// place that turns `ModelProperties` into baked, weighted meshes.
+fn bake_variant_group(
+    candidates: &[ModelProperties],
+    resource_provider: &dyn ResourceProvider,
+    block_atlas: &Atlas,
+) -> Result<Vec<WeightedVariant>, MeshBakeError> {
+    candidates
+        .iter()
+        .map(|properties| {
+            Ok(WeightedVariant {
+                mesh: Arc::new(ModelMesh::bake(
+                    [properties],
+                    resource_provider,
+                    block_atlas,
+                )?),
+                weight: properties.weight.unwrap_or(1),
+            })
+        })
+        .collect::<Result<Vec<_>, MeshBakeError>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_uv_quad_is_a_no_op_at_0_degrees() {
+        let uv: UV = ((0, 0), (16, 16));
+        assert_eq!(rotate_uv_quad(uv, 0), uv);
+    }
+
+    #[test]
+    fn rotate_uv_quad_rotates_about_the_face_center() {
+        //A 16x8 rect rotated 90 degrees about its center swaps width/height,
+        //landing back on the atlas at the same center point.
+        let uv: UV = ((100, 200), (116, 208));
+        assert_eq!(rotate_uv_quad(uv, 90), ((112, 196), (104, 212)));
+        assert_eq!(rotate_uv_quad(uv, 180), ((116, 208), (100, 200)));
+        assert_eq!(rotate_uv_quad(uv, 270), ((104, 212), (112, 196)));
+    }
+
+    fn element_rotation(json: &str) -> schemas::models::ElementRotation {
+        serde_json::from_str(json).expect("valid element rotation JSON")
+    }
+
+    #[test]
+    fn element_rotation_matrix_is_identity_at_zero_degrees() {
+        let rotation = element_rotation(
+            r#"{"origin": [8.0, 8.0, 8.0], "axis": "y", "angle": 0.0, "rescale": false}"#,
+        );
+
+        let matrix = element_rotation_matrix(&rotation);
+        let point = Vector4::new(1.0, 0.0, 0.0, 1.0);
+
+        let rotated = matrix * point;
+        assert!((rotated.x - point.x).abs() < 1e-5);
+        assert!((rotated.y - point.y).abs() < 1e-5);
+        assert!((rotated.z - point.z).abs() < 1e-5);
+    }
+
+    #[test]
+    fn element_rotation_matrix_rotates_about_its_origin() {
+        //Vertex-space mirrors x (`1.0 - x / 16.0`), so origin [8,8,8] sits at
+        //x = 1.0 - 8.0/16.0 = 0.5 - the rotation axis passes through the
+        //model's vertical centerline.
+        let rotation = element_rotation(
+            r#"{"origin": [8.0, 8.0, 8.0], "axis": "y", "angle": 90.0, "rescale": false}"#,
+        );
+
+        let matrix = element_rotation_matrix(&rotation);
+
+        //A point half a block to the +x side of the origin...
+        let point = Vector4::new(1.0, 0.5, 0.5, 1.0);
+        let rotated = matrix * point;
+
+        //...ends up displaced on the z axis instead, at the same distance
+        //from the origin, once rotated 90 degrees about y.
+        assert!((rotated.x - 0.5).abs() < 1e-4);
+        assert!((rotated.y - 0.5).abs() < 1e-5);
+        assert!((rotated.z - 0.0).abs() < 1e-4 || (rotated.z - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn element_rotation_matrix_rescale_widens_perpendicular_axes() {
+        let rotation = element_rotation(
+            r#"{"origin": [8.0, 8.0, 8.0], "axis": "y", "angle": 45.0, "rescale": true}"#,
+        );
+
+        let matrix = element_rotation_matrix(&rotation);
+
+        //Rescaling by 1/cos(45deg) on the x/z axes should move a point
+        //further from the origin than the unscaled rotation would.
+        let unscaled = element_rotation_matrix(&element_rotation(
+            r#"{"origin": [8.0, 8.0, 8.0], "axis": "y", "angle": 45.0, "rescale": false}"#,
+        ));
+
+        let distance_from_origin = |m: Matrix4<f32>| {
+            let p: Vector3<f32> = (m * Vector4::new(1.0, 0.5, 0.5, 1.0)).truncate();
+            let origin = Vector3::new(0.5, 0.5, 0.5);
+            ((p.x - origin.x).powi(2) + (p.y - origin.y).powi(2) + (p.z - origin.z).powi(2)).sqrt()
+        };
+
+        assert!(distance_from_origin(matrix) > distance_from_origin(unscaled));
+    }
+}
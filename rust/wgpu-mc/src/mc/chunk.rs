@@ -11,18 +11,20 @@
 use arc_swap::ArcSwap;
 use parking_lot::{Mutex, RwLock};
 use range_alloc::RangeAllocator;
-use std::collections::HashMap;
+use rayon::prelude::*;
+use std::collections::{BinaryHeap, HashMap};
 use std::fmt::Debug;
 use std::mem::size_of;
 use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{BufferAddress, BufferDescriptor, BufferUsages};
 
 use crate::mc::block::{
-    BlockMeshVertex, BlockstateKey, ChunkBlockState, CubeOrComplexMesh, ModelMesh,
+    BlockMeshVertex, BlockModelFaces, BlockstateKey, ChunkBlockState, Face, ModelMesh,
 };
-use crate::mc::BlockManager;
+use crate::mc::{BlockManager, MinecraftState};
 use crate::render::pipeline::Vertex;
 
 use crate::{WgpuState, WmRenderer};
@@ -42,6 +44,26 @@ pub type ChunkPos = [i32; 2];
 pub struct ChunkAllocation {
     pub buffer: Arc<wgpu::Buffer>,
     pub allocator: RwLock<RangeAllocator<usize>>,
+    /// Bytes currently handed out by [Self::allocator]. `RangeAllocator`
+    /// doesn't expose its own occupancy, so this is kept in lockstep with
+    /// every [RangeAllocator::allocate_range]/`free_range` call and backs
+    /// [ChunkManager::allocation_stats].
+    pub(crate) used_bytes: AtomicUsize,
+}
+
+/// Occupancy of the fixed-size [ChunkAllocation] buffer, returned by
+/// [ChunkManager::allocation_stats] so callers can decide whether
+/// [ChunkManager::compact] is worth running.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkAllocationStats {
+    pub used_bytes: usize,
+    pub capacity_bytes: usize,
+    /// Free bytes that aren't part of one contiguous block at the tail of
+    /// the buffer, i.e. `capacity - used - trailing_free`. Nonzero means
+    /// live ranges are scattered with gaps between them, so a large
+    /// allocation could fail with `used_bytes` well under `capacity_bytes`
+    /// until [ChunkManager::compact] packs everything back down.
+    pub fragmented_bytes: usize,
 }
 
 pub struct ChunkManager {
@@ -64,9 +86,182 @@ impl ChunkManager {
                     mapped_at_creation: false,
                 })),
                 allocator: RwLock::new(RangeAllocator::new(0..CHUNK_ALLOCATOR_SIZE)),
+                used_bytes: AtomicUsize::new(0),
             },
         }
     }
+
+    /// Removes `pos` from [Self::loaded_chunks] and frees every range it had
+    /// allocated out of [Self::chunk_allocation], so unloading a chunk
+    /// actually gives its space back instead of leaking it until the
+    /// allocator runs out and nothing can be baked anymore.
+    pub fn unload_chunk(&self, pos: ChunkPos) {
+        let Some(chunk) = self.loaded_chunks.write().remove(&pos) else {
+            return;
+        };
+
+        free_layer_ranges(
+            &self.chunk_allocation,
+            chunk.load().baked_layers.read().values(),
+        );
+    }
+
+    /// Current occupancy of [Self::chunk_allocation]'s backing buffer.
+    pub fn allocation_stats(&self) -> ChunkAllocationStats {
+        let used_bytes = self
+            .chunk_allocation
+            .used_bytes
+            .load(Ordering::Acquire);
+
+        let trailing_end = self
+            .loaded_chunks
+            .read()
+            .values()
+            .map(|chunk| {
+                chunk
+                    .load()
+                    .baked_layers
+                    .read()
+                    .values()
+                    .map(|range| range.end)
+                    .max()
+                    .unwrap_or(0)
+            })
+            .max()
+            .unwrap_or(0);
+
+        ChunkAllocationStats {
+            used_bytes,
+            capacity_bytes: CHUNK_ALLOCATOR_SIZE,
+            //Everything below the highest live range's end is either used or
+            //trapped between live ranges; only space past it is free in one
+            //contiguous block.
+            fragmented_bytes: trailing_end.saturating_sub(used_bytes),
+        }
+    }
+
+    /// Packs every loaded chunk's vertex ranges tightly against the start of
+    /// [Self::chunk_allocation]'s buffer, reclaiming whatever space
+    /// [Self::allocation_stats] reports as fragmented. Copies the live bytes
+    /// into their new positions via a scratch buffer, then rebuilds the
+    /// allocator and every [Chunk::baked_layers] entry to match.
+    ///
+    /// Goes through a scratch buffer rather than copying the live buffer onto
+    /// itself: a survivor's new offset is always `<=` its old one, so a
+    /// direct same-buffer copy routinely has source and destination
+    /// overlapping (e.g. a range moving back by less than its own length),
+    /// which `copy_buffer_to_buffer` rejects as a validation error.
+    pub fn compact(&self, wm: &WmRenderer) {
+        let loaded_chunks = self.loaded_chunks.read();
+
+        let mut live: Vec<(ChunkPos, String, Range<usize>)> = loaded_chunks
+            .iter()
+            .flat_map(|(pos, chunk)| {
+                chunk
+                    .load()
+                    .baked_layers
+                    .read()
+                    .iter()
+                    .map(|(name, range)| (*pos, name.clone(), range.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        live.sort_by_key(|(_, _, range)| range.start);
+
+        let mut cursor = 0usize;
+        let mut new_ranges: HashMap<ChunkPos, HashMap<String, Range<usize>>> = HashMap::new();
+        //Every live range, old and new position, regardless of whether it
+        //actually moved - all of them have to land in the scratch buffer, or
+        //the bulk copy-back at the end would read uninitialized scratch
+        //bytes for whichever ranges happened to already be in place.
+        let mut copies: Vec<(Range<usize>, Range<usize>)> = Vec::with_capacity(live.len());
+
+        for (pos, name, old_range) in &live {
+            let len = old_range.end - old_range.start;
+            let new_range = cursor..cursor + len;
+
+            copies.push((old_range.clone(), new_range.clone()));
+
+            new_ranges
+                .entry(*pos)
+                .or_default()
+                .insert(name.clone(), new_range);
+
+            cursor += len;
+        }
+
+        if cursor > 0 {
+            let scratch = wm.wgpu_state.device.create_buffer(&BufferDescriptor {
+                label: Some("chunk allocation compaction scratch"),
+                size: cursor as BufferAddress,
+                usage: BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+            let mut encoder = wm
+                .wgpu_state
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("chunk allocation compaction"),
+                });
+
+            //Every copy here is buffer -> scratch, so unlike a same-buffer
+            //shift, overlapping old/new ranges can never alias each other.
+            for (old_range, new_range) in &copies {
+                encoder.copy_buffer_to_buffer(
+                    &self.chunk_allocation.buffer,
+                    old_range.start as BufferAddress,
+                    &scratch,
+                    new_range.start as BufferAddress,
+                    (old_range.end - old_range.start) as BufferAddress,
+                );
+            }
+
+            //One final scratch -> buffer copy brings the compacted layout
+            //back, again never aliasing since the two buffers are distinct.
+            encoder.copy_buffer_to_buffer(
+                &scratch,
+                0,
+                &self.chunk_allocation.buffer,
+                0,
+                cursor as BufferAddress,
+            );
+
+            wm.wgpu_state.queue.submit(Some(encoder.finish()));
+        }
+
+        *self.chunk_allocation.allocator.write() = RangeAllocator::new(0..CHUNK_ALLOCATOR_SIZE);
+        if cursor > 0 {
+            self.chunk_allocation
+                .allocator
+                .write()
+                .allocate_range(cursor)
+                .unwrap();
+        }
+
+        for (pos, chunk) in loaded_chunks.iter() {
+            if let Some(ranges) = new_ranges.get(pos) {
+                *chunk.load().baked_layers.write() = ranges.clone();
+            }
+        }
+    }
+}
+
+/// Frees every range in `ranges` from `allocation`'s allocator, keeping
+/// [ChunkAllocation::used_bytes] in sync. Shared by [ChunkManager::unload_chunk]
+/// and anywhere a chunk's old geometry is being replaced before new geometry
+/// is written in its place.
+pub(crate) fn free_layer_ranges<'a>(
+    allocation: &ChunkAllocation,
+    ranges: impl Iterator<Item = &'a Range<usize>>,
+) {
+    let mut allocator = allocation.allocator.write();
+    for range in ranges {
+        allocation
+            .used_bytes
+            .fetch_sub(range.end - range.start, Ordering::AcqRel);
+        allocator.free_range(range.clone());
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -81,12 +276,27 @@ pub trait BlockStateProvider: Send + Sync + Debug {
     fn get_state(&self, x: i32, y: i16, z: i32) -> ChunkBlockState;
 
     fn is_section_empty(&self, index: usize) -> bool;
+
+    /// Resolves the final RGB color a face with the given `tint_index` should
+    /// be multiplied by at this position, e.g. the biome grass/foliage color.
+    /// Implementations backed by real world data should consult a
+    /// [BiomeColorProvider](crate::mc::tint::BiomeColorProvider); `tint_index < 0`
+    /// always means "no tint", so implementors can just return `[255; 3]`.
+    fn get_block_color(&self, x: i32, y: i16, z: i32, tint_index: i32) -> [u8; 3];
+
+    /// The fluid (water/lava) occupying this position, if any. Solid blocks
+    /// and air should return `None`. Backed by the same world data as
+    /// [Self::get_state], this is queried by [crate::mc::fluid::bake_fluid]
+    /// both for the block itself and its neighbors, to slope the fluid surface.
+    fn get_fluid_state(&self, x: i32, y: i16, z: i32) -> Option<crate::mc::fluid::FluidState>;
 }
 
 pub trait RenderLayer: Send + Sync {
     fn filter(&self) -> fn(BlockstateKey) -> bool;
 
-    fn mapper(&self) -> fn(&BlockMeshVertex, f32, f32, f32) -> Vertex;
+    /// The last `f32` is this vertex's ambient-occlusion brightness in
+    /// `0.0..=1.0` (see [ao_brightness]), `1.0` meaning fully lit.
+    fn mapper(&self) -> fn(&BlockMeshVertex, f32, f32, f32, [u8; 3], f32, u32) -> Vertex;
 
     fn name(&self) -> &str;
 }
@@ -116,6 +326,23 @@ impl Chunk {
         block_manager: &BlockManager,
         provider: &T,
     ) {
+        let block_atlas = wm
+            .mc
+            .texture_manager
+            .atlases
+            .load()
+            .get(crate::render::pipeline::BLOCK_ATLAS)
+            .unwrap()
+            .load();
+
+        //This chunk is being re-baked; free whatever it had allocated last
+        //time before handing out new ranges, or every re-bake leaks its
+        //previous geometry's space permanently.
+        free_layer_ranges(
+            &wm.mc.chunks.chunk_allocation,
+            self.baked_layers.read().values(),
+        );
+
         let baked_layers = layers
             .iter()
             .map(|layer| {
@@ -125,12 +352,19 @@ impl Chunk {
                     layer.mapper(),
                     layer.filter(),
                     provider,
+                    &block_atlas,
                 );
 
                 let range = {
-                    let mut allocator = wm.mc.chunks.chunk_allocation.allocator.write();
                     let size = verts.len() * size_of::<Vertex>();
-                    allocator.allocate_range(size).unwrap()
+                    let mut allocator = wm.mc.chunks.chunk_allocation.allocator.write();
+                    let range = allocator.allocate_range(size).unwrap();
+                    wm.mc
+                        .chunks
+                        .chunk_allocation
+                        .used_bytes
+                        .fetch_add(size, Ordering::AcqRel);
+                    range
                 };
 
                 wm.wgpu_state.queue.write_buffer(
@@ -156,15 +390,24 @@ fn is_block_not_fully_opaque(
     y: i16,
     z: i32,
 ) -> bool {
-    let state = get_block(block_manager, state_provider.get_state(x, y, z));
+    let state = get_block(block_manager, state_provider.get_state(x, y, z), x, y, z);
 
     match state {
-        Some(mesh) => mesh.models[0].1,
+        //A model only occludes the faces behind it when it's a full cube;
+        //anything else (slabs, stairs, cross models, ...) may leave some of
+        //the space behind it visible, so conservatively still render there.
+        Some(mesh) => !mesh.is_cube,
         None => true,
     }
 }
 
-fn get_block(block_manager: &BlockManager, state: ChunkBlockState) -> Option<Arc<ModelMesh>> {
+fn get_block(
+    block_manager: &BlockManager,
+    state: ChunkBlockState,
+    x: i32,
+    y: i16,
+    z: i32,
+) -> Option<Arc<ModelMesh>> {
     let key = match state {
         ChunkBlockState::Air => return None,
         ChunkBlockState::State(key) => key,
@@ -175,25 +418,546 @@ fn get_block(block_manager: &BlockManager, state: ChunkBlockState) -> Option<Arc
             .blocks
             .get_index(key.block as usize)?
             .1
-            .get_model(key.augment),
+            .get_model(key.augment, x, y, z),
     )
 }
 
+/// Which of a cube's six faces is being considered, and the coordinate math
+/// that goes with that: the world-space normal it faces, and how a chunk
+/// section's blocks map onto the 2D mask [bake_greedy_slice] sweeps.
+#[derive(Clone, Copy)]
+enum Facing {
+    North,
+    South,
+    East,
+    West,
+    Up,
+    Down,
+}
+
+impl Facing {
+    const ALL: [Facing; 6] = [
+        Facing::North,
+        Facing::South,
+        Facing::East,
+        Facing::West,
+        Facing::Up,
+        Facing::Down,
+    ];
+
+    /// The direction a face points in, i.e. where its neighbor is.
+    fn normal(self) -> (i32, i16, i32) {
+        match self {
+            Facing::North => (0, 0, -1),
+            Facing::South => (0, 0, 1),
+            Facing::East => (1, 0, 0),
+            Facing::West => (-1, 0, 0),
+            Facing::Up => (0, 1, 0),
+            Facing::Down => (0, -1, 0),
+        }
+    }
+
+    fn face(self, faces: &BlockModelFaces) -> Option<Face> {
+        match self {
+            Facing::North => faces.north,
+            Facing::South => faces.south,
+            Facing::East => faces.east,
+            Facing::West => faces.west,
+            Facing::Up => faces.up,
+            Facing::Down => faces.down,
+        }
+    }
+
+    /// Which [BlockMeshVertex::position] component is this facing's mask
+    /// `u`/`v` axis, so a merged quad can stretch a unit face's 0/1 corner
+    /// by the run's width/height along the matching axis.
+    fn in_plane_axes(self) -> (usize, usize) {
+        match self {
+            Facing::Up | Facing::Down => (0, 2),
+            Facing::East | Facing::West => (2, 1),
+            Facing::North | Facing::South => (0, 1),
+        }
+    }
+
+    /// Maps a `(layer, u, v)` cell of this facing's mask to the chunk-local
+    /// `(x, y, z)` of the block it covers. `layer` runs along the facing's
+    /// own axis (0..16 within the section); `u`/`v` run along the other two.
+    fn mask_cell_to_local(self, section_base_y: i16, layer: usize, u: usize, v: usize) -> (i32, i16, i32) {
+        match self {
+            Facing::Up | Facing::Down => (u as i32, section_base_y + layer as i16, v as i32),
+            Facing::East | Facing::West => (layer as i32, section_base_y + v as i16, u as i32),
+            Facing::North | Facing::South => (u as i32, section_base_y + v as i16, layer as i32),
+        }
+    }
+}
+
+/// A `(dx, dy, dz)` offset of `delta` along world axis `axis` (`0` = x, `1` =
+/// y, `2` = z), the other two axes left at zero. Used to walk from a face's
+/// own block out to the neighbors a corner's AO is sampled from.
+fn axis_offset(axis: usize, delta: i32) -> (i32, i16, i32) {
+    match axis {
+        0 => (delta, 0, 0),
+        1 => (0, delta as i16, 0),
+        2 => (0, 0, delta),
+        _ => unreachable!("in-plane axis index is always 0, 1 or 2"),
+    }
+}
+
+/// The classic 4-level vertex AO: `3` is fully lit (no occluding neighbor),
+/// down to `0`. `side1`/`side2` are the two blocks edge-adjacent to this
+/// corner (sharing an edge with the face), `corner` is the one diagonally
+/// adjacent; all three are sampled one layer past the face along its normal.
+/// Two occupied sides force the darkest level regardless of the corner,
+/// since the corner block is usually hidden behind them anyway.
+fn vertex_ao_level(side1_occupied: bool, side2_occupied: bool, corner_occupied: bool) -> u8 {
+    if side1_occupied && side2_occupied {
+        return 0;
+    }
+
+    3 - (side1_occupied as u8 + side2_occupied as u8 + corner_occupied as u8)
+}
+
+/// AO level for the corner of `facing`'s face (on the block at `x, y, z`)
+/// that lies toward `corner_u`/`corner_v` (each `-1` or `1`) along the
+/// facing's two in-plane axes.
+fn corner_ao_level(
+    block_manager: &BlockManager,
+    state_provider: &impl BlockStateProvider,
+    facing: Facing,
+    x: i32,
+    y: i16,
+    z: i32,
+    corner_u: i32,
+    corner_v: i32,
+) -> u8 {
+    let (nx, ny, nz) = facing.normal();
+    let (axis_u, axis_v) = facing.in_plane_axes();
+    let (ux, uy, uz) = axis_offset(axis_u, corner_u);
+    let (vx, vy, vz) = axis_offset(axis_v, corner_v);
+
+    let occupied = |dx: i32, dy: i16, dz: i32| {
+        !is_block_not_fully_opaque(block_manager, state_provider, x + nx + dx, y + ny + dy, z + nz + dz)
+    };
+
+    let side1 = occupied(ux, uy, uz);
+    let side2 = occupied(vx, vy, vz);
+    let corner = occupied(ux + vx, uy + vy, uz + vz);
+
+    vertex_ao_level(side1, side2, corner)
+}
+
+/// The AO level for one template vertex of `facing`'s face, working out
+/// which corner it is from which side of the unit cube its in-plane
+/// coordinates sit on (`< 0.5` is the `-1` side, `>= 0.5` is the `1` side).
+fn template_vertex_ao_level(
+    block_manager: &BlockManager,
+    state_provider: &impl BlockStateProvider,
+    facing: Facing,
+    x: i32,
+    y: i16,
+    z: i32,
+    vertex: &BlockMeshVertex,
+) -> u8 {
+    let (axis_u, axis_v) = facing.in_plane_axes();
+    let corner_u = if vertex.position[axis_u] >= 0.5 { 1 } else { -1 };
+    let corner_v = if vertex.position[axis_v] >= 0.5 { 1 } else { -1 };
+
+    corner_ao_level(block_manager, state_provider, facing, x, y, z, corner_u, corner_v)
+}
+
+/// Normalizes a [vertex_ao_level] (`0..=3`) to the `0.0..=1.0` brightness a
+/// [RenderLayer::mapper] multiplies a vertex's color by.
+fn ao_brightness(level: u8) -> f32 {
+    level as f32 / 3.0
+}
+
+/// Reorders a face's 4 template-order vertices (and their parallel AO
+/// levels) so the triangulation's shared diagonal connects the brighter
+/// pair of opposite corners instead of the darker one, avoiding the
+/// classic AO anisotropy artifact where interpolating across a dark corner
+/// that isn't part of the shared edge makes it look uniformly dark.
+/// Assumes quads are drawn as two triangles `(0, 1, 2)` / `(0, 2, 3)` over
+/// these 4 vertices, same as every other consumer of [BlockModelFaces]'s
+/// 4-vertex-per-face layout.
+fn flip_toward_brighter_diagonal<A: Copy>(vertices: [A; 4], ao: [u8; 4]) -> ([A; 4], [u8; 4]) {
+    let opposite_pair_a = ao[0] as u32 + ao[2] as u32;
+    let opposite_pair_b = ao[1] as u32 + ao[3] as u32;
+
+    if opposite_pair_b > opposite_pair_a {
+        (
+            [vertices[1], vertices[2], vertices[3], vertices[0]],
+            [ao[1], ao[2], ao[3], ao[0]],
+        )
+    } else {
+        (vertices, ao)
+    }
+}
+
+/// The merge-relevant identity of a rendered cube face: two faces only
+/// combine into one quad when the block they came from, the *specific*
+/// weighted variant [Block::get_model] selected for them, the color they'd
+/// be tinted, and their 4 corners' AO levels are all identical, since those
+/// are the only things that differ between [BlockMeshVertex]es generated
+/// from the same unit-cube template. Requiring matching AO keeps a merged
+/// quad's baked-in corner darkening from spreading across blocks it doesn't
+/// apply to; requiring matching `variant` keeps chunk0-5's position-seeded
+/// variant selection (e.g. grass/dirt/stone texture variance) from being
+/// flattened to whichever variant the run's origin block happened to roll.
+///
+/// `variant` is the selected [Arc<ModelMesh>]'s address rather than some
+/// smaller index, since [Block::get_model] doesn't expose which candidate
+/// index it picked - only the resulting mesh, and identical variants are
+/// always served from the same cached `Arc`.
+///
+/// Doesn't include `tex_index`: two faces with equal `state` and `variant`
+/// come from the same baked mesh's face template, so under either
+/// [crate::BlockTextureBackend] their `tex_index` already matches.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct GreedyFaceKey {
+    state: BlockstateKey,
+    variant: usize,
+    color: [u8; 3],
+    ao: [u8; 4],
+}
+
+/// Greedily merges coplanar, axis-aligned faces of full-cube blocks into the
+/// fewest quads that still render identically, instead of one quad per block
+/// face. Complex (non-cube) models - slabs, stairs, cross models, anything
+/// [ModelMesh::is_cube] is false for - aren't eligible and are still emitted
+/// one element at a time by [bake_layer]'s main loop.
+fn bake_cube_faces_greedy<
+    T,
+    Provider: BlockStateProvider,
+    Filter: Fn(BlockstateKey) -> bool,
+    Mapper: Fn(&BlockMeshVertex, f32, f32, f32, [u8; 3], f32, u32) -> T,
+>(
+    block_manager: &BlockManager,
+    chunk: &Chunk,
+    mapper: &Mapper,
+    filter: &Filter,
+    state_provider: &Provider,
+    vertices: &mut Vec<T>,
+) {
+    for section_index in 0..CHUNK_SECTIONS_PER {
+        if state_provider.is_section_empty(section_index) {
+            continue;
+        }
+
+        let section_base_y = (section_index * CHUNK_SECTION_HEIGHT) as i16;
+
+        for facing in Facing::ALL.iter().copied() {
+            bake_greedy_slice(
+                block_manager,
+                chunk,
+                mapper,
+                filter,
+                state_provider,
+                facing,
+                section_base_y,
+                vertices,
+            );
+        }
+    }
+}
+
+/// A single facing's worth of greedy meshing for one chunk section: builds a
+/// 16x16 mask per layer along the facing's axis, then sweeps it the usual
+/// way (find a non-empty cell, grow a rectangle of matching cells, emit one
+/// quad, clear the cells it covered) so merging never needs to look past the
+/// blocks it's currently scanning, and never crosses a section boundary.
+#[allow(clippy::too_many_arguments)]
+fn bake_greedy_slice<
+    T,
+    Provider: BlockStateProvider,
+    Filter: Fn(BlockstateKey) -> bool,
+    Mapper: Fn(&BlockMeshVertex, f32, f32, f32, [u8; 3], f32, u32) -> T,
+>(
+    block_manager: &BlockManager,
+    chunk: &Chunk,
+    mapper: &Mapper,
+    filter: &Filter,
+    state_provider: &Provider,
+    facing: Facing,
+    section_base_y: i16,
+    vertices: &mut Vec<T>,
+) {
+    const SIZE: usize = CHUNK_WIDTH;
+    let (dx, dy, dz) = facing.normal();
+
+    for layer in 0..SIZE {
+        let mut mask: Vec<Option<GreedyFaceKey>> = (0..SIZE * SIZE)
+            .map(|i| {
+                let u = i % SIZE;
+                let v = i / SIZE;
+                let (local_x, local_y, local_z) =
+                    facing.mask_cell_to_local(section_base_y, layer, u, v);
+
+                let absolute_x = chunk.pos[0] * 16 + local_x;
+                let absolute_z = chunk.pos[1] * 16 + local_z;
+
+                let state = state_provider.get_state(absolute_x, local_y, absolute_z);
+                if state.is_air() {
+                    return None;
+                }
+
+                let state_key = match state {
+                    ChunkBlockState::Air => unreachable!(),
+                    ChunkBlockState::State(key) => key,
+                };
+
+                if !filter(state_key) {
+                    return None;
+                }
+
+                //Fluids aren't full cubes and are meshed separately.
+                if state_provider
+                    .get_fluid_state(absolute_x, local_y, absolute_z)
+                    .is_some()
+                {
+                    return None;
+                }
+
+                let mesh = get_block(block_manager, state, absolute_x, local_y, absolute_z)?;
+                if !mesh.is_cube {
+                    return None;
+                }
+
+                let face = facing.face(&mesh.mesh[0])?;
+
+                let neighbor_visible = is_block_not_fully_opaque(
+                    block_manager,
+                    state_provider,
+                    absolute_x + dx,
+                    local_y + dy,
+                    absolute_z + dz,
+                );
+                if !neighbor_visible {
+                    return None;
+                }
+
+                let color = if face.tint_index >= 0 {
+                    state_provider.get_block_color(absolute_x, local_y, absolute_z, face.tint_index)
+                } else {
+                    [255; 3]
+                };
+
+                let start = face.vert_index as usize;
+                let template = &mesh.mesh[0].vertices[start..start + 4];
+                let ao = [
+                    template_vertex_ao_level(
+                        block_manager,
+                        state_provider,
+                        facing,
+                        absolute_x,
+                        local_y,
+                        absolute_z,
+                        &template[0],
+                    ),
+                    template_vertex_ao_level(
+                        block_manager,
+                        state_provider,
+                        facing,
+                        absolute_x,
+                        local_y,
+                        absolute_z,
+                        &template[1],
+                    ),
+                    template_vertex_ao_level(
+                        block_manager,
+                        state_provider,
+                        facing,
+                        absolute_x,
+                        local_y,
+                        absolute_z,
+                        &template[2],
+                    ),
+                    template_vertex_ao_level(
+                        block_manager,
+                        state_provider,
+                        facing,
+                        absolute_x,
+                        local_y,
+                        absolute_z,
+                        &template[3],
+                    ),
+                ];
+
+                Some(GreedyFaceKey {
+                    state: state_key,
+                    variant: Arc::as_ptr(&mesh) as usize,
+                    color,
+                    ao,
+                })
+            })
+            .collect();
+
+        let mut v = 0;
+        while v < SIZE {
+            let mut u = 0;
+            while u < SIZE {
+                let Some(key) = mask[v * SIZE + u] else {
+                    u += 1;
+                    continue;
+                };
+
+                let mut width = 1;
+                while u + width < SIZE && mask[v * SIZE + u + width] == Some(key) {
+                    width += 1;
+                }
+
+                let mut height = 1;
+                'grow_height: while v + height < SIZE {
+                    for w in 0..width {
+                        if mask[(v + height) * SIZE + u + w] != Some(key) {
+                            break 'grow_height;
+                        }
+                    }
+                    height += 1;
+                }
+
+                emit_greedy_quad(
+                    block_manager,
+                    chunk,
+                    mapper,
+                    facing,
+                    section_base_y,
+                    layer,
+                    u,
+                    v,
+                    width,
+                    height,
+                    key,
+                    vertices,
+                );
+
+                for hh in 0..height {
+                    for ww in 0..width {
+                        mask[(v + hh) * SIZE + u + ww] = None;
+                    }
+                }
+
+                u += width;
+            }
+            v += 1;
+        }
+    }
+}
+
+/// Emits the one quad a merged `width x height` run of matching faces
+/// collapses into, by taking the origin block's own unit-cube face template
+/// and stretching its two in-plane corners (the ones at `1.0`, per
+/// [Facing::in_plane_axes]) by the run's width/height - reusing the same
+/// vertex positions/winding [ModelMesh::bake] already baked in, just scaled.
+///
+/// Texture coordinates are left exactly as the origin block's template
+/// baked them rather than scaled/tiled with the run: every block texture
+/// lives in a shared atlas whose sampler only clamps safely within one
+/// texture's own cell, so multiplying a UV past that cell's extent (to make
+/// it "repeat" across the merged run) would sample whatever texture happens
+/// to sit next to it in the atlas instead. The merged quad therefore shows
+/// one stretched copy of the origin block's texture across its whole
+/// footprint rather than a tiled repeat per block - correct sampling over
+/// UV-bleed, at the cost of texture fidelity on large runs. A texture-array
+/// backend ([crate::BlockTextureBackend::TextureArray]) wouldn't share this
+/// problem, since each layer is its own texture free to wrap.
+#[allow(clippy::too_many_arguments)]
+fn emit_greedy_quad<
+    T,
+    Mapper: Fn(&BlockMeshVertex, f32, f32, f32, [u8; 3], f32, u32) -> T,
+>(
+    block_manager: &BlockManager,
+    chunk: &Chunk,
+    mapper: &Mapper,
+    facing: Facing,
+    section_base_y: i16,
+    layer: usize,
+    u: usize,
+    v: usize,
+    width: usize,
+    height: usize,
+    key: GreedyFaceKey,
+    vertices: &mut Vec<T>,
+) {
+    let (local_x, local_y, local_z) = facing.mask_cell_to_local(section_base_y, layer, u, v);
+    let absolute_x = chunk.pos[0] * 16 + local_x;
+    let absolute_z = chunk.pos[1] * 16 + local_z;
+
+    let Some(mesh) = block_manager
+        .blocks
+        .get_index(key.state.block as usize)
+        .map(|(_, block)| block.get_model(key.state.augment, absolute_x, local_y, absolute_z))
+    else {
+        return;
+    };
+
+    let Some(face) = facing.face(&mesh.mesh[0]) else {
+        return;
+    };
+
+    let start = face.vert_index as usize;
+    let template = &mesh.mesh[0].vertices[start..start + 4];
+    let (u_axis, v_axis) = facing.in_plane_axes();
+
+    let scaled: Vec<BlockMeshVertex> = template
+        .iter()
+        .map(|vert| {
+            let mut position = vert.position;
+            position[u_axis] *= width as f32;
+            position[v_axis] *= height as f32;
+
+            BlockMeshVertex {
+                position,
+                tex_coords: vert.tex_coords,
+                normal: vert.normal,
+                animation_uv_offset: vert.animation_uv_offset,
+                tex_index: vert.tex_index,
+            }
+        })
+        .collect();
+
+    let scaled: [BlockMeshVertex; 4] = scaled.try_into().unwrap();
+    let (scaled, ao) = flip_toward_brighter_diagonal(scaled, key.ao);
+
+    for (vert, level) in scaled.iter().zip(ao) {
+        vertices.push(mapper(
+            vert,
+            local_x as f32,
+            local_y as f32,
+            local_z as f32,
+            key.color,
+            ao_brightness(level),
+            vert.tex_index,
+        ));
+    }
+}
+
 pub fn bake_layer<
     T,
     Provider: BlockStateProvider,
     Filter: Fn(BlockstateKey) -> bool,
-    Mapper: Fn(&BlockMeshVertex, f32, f32, f32) -> T,
+    Mapper: Fn(&BlockMeshVertex, f32, f32, f32, [u8; 3], f32, u32) -> T,
 >(
     block_manager: &BlockManager,
     chunk: &Chunk,
     mapper: Mapper,
     filter: Filter,
     state_provider: &Provider,
-) -> (Vec<T>, Vec<u32>) {
+    block_atlas: &crate::render::atlas::Atlas,
+) -> Vec<T> {
     //Generates the mesh for this chunk, culling faces whenever possible
     let mut vertices = Vec::new();
-    let mut indices = Vec::new();
+
+    //Full-cube blocks are meshed up front, merging runs of identical,
+    //coplanar faces into single quads; the loop below then only has to
+    //handle non-cube ("complex") models face-by-face.
+    bake_cube_faces_greedy(
+        block_manager,
+        chunk,
+        &mapper,
+        &filter,
+        state_provider,
+        &mut vertices,
+    );
 
     let mut block_index = 0;
 
@@ -234,67 +998,293 @@ pub fn bake_layer<
             continue;
         }
 
-        let mesh = get_block(block_manager, block_state).unwrap();
+        //Fluids can't be expressed as a regular block model (their top face
+        //slopes with neighboring fluid levels), so they're meshed separately
+        //and skip the cube/complex dispatch below entirely.
+        if let Some(fluid_state) = state_provider.get_fluid_state(absolute_x, y, absolute_z) {
+            let fluid_verts = crate::mc::fluid::bake_fluid(
+                fluid_state,
+                state_provider,
+                block_atlas,
+                absolute_x,
+                y,
+                absolute_z,
+            );
 
-        // TODO: randomly select a mesh if there are multiple
+            //Tint index 0 is vanilla's biome watercolor; lava isn't tinted.
+            let tint_index = match fluid_state.kind {
+                crate::mc::fluid::FluidKind::Water => 0,
+                crate::mc::fluid::FluidKind::Lava => -1,
+            };
+            let color = state_provider.get_block_color(absolute_x, y, absolute_z, tint_index);
 
-        match &mesh.models[0].0 {
-            CubeOrComplexMesh::Cube(model) => {
-                let baked_should_render_face = |x_: i32, y_: i16, z_: i32| {
-                    is_block_not_fully_opaque(block_manager, state_provider, x_, y_, z_)
-                };
+            //Fluid surfaces aren't full cubes and don't go through the
+            //corner-neighbor sampling [template_vertex_ao_level] assumes, so
+            //they're left fully lit for now.
+            vertices.extend(
+                fluid_verts
+                    .iter()
+                    .map(|v| mapper(v, x as f32, y as f32, z as f32, color, 1.0, v.tex_index)),
+            );
 
-                let render_east = baked_should_render_face(absolute_x + 1, y, absolute_z);
-                let render_west = baked_should_render_face(absolute_x - 1, y, absolute_z);
-                let render_up = baked_should_render_face(absolute_x, y + 1, absolute_z);
-                let render_down = baked_should_render_face(absolute_x, y - 1, absolute_z);
-                let render_south = baked_should_render_face(absolute_x, y, absolute_z + 1);
-                let render_north = baked_should_render_face(absolute_x, y, absolute_z - 1);
+            continue;
+        }
 
-                let add_face = || {
-                    render_east
-                };
+        let mesh = get_block(block_manager, block_state, absolute_x, y, absolute_z).unwrap();
 
-                if render_north {
+        //Full cubes were already meshed (and merged where possible) by
+        //bake_cube_faces_greedy above; only complex (non-cube) models still
+        //need emitting here, one element at a time.
+        if mesh.is_cube {
+            continue;
+        }
 
-                }
-                if render_east {
+        vertices.extend(
+            mesh.mesh
+                .iter()
+                .flat_map(|faces| {
+                    [
+                        (Facing::North, faces.north),
+                        (Facing::East, faces.east),
+                        (Facing::South, faces.south),
+                        (Facing::West, faces.west),
+                        (Facing::Up, faces.up),
+                        (Facing::Down, faces.down),
+                    ]
+                    .into_iter()
+                    .filter_map(|(facing, face)| face.map(|face| (facing, face)))
+                    .map(move |(facing, face)| {
+                        let start = face.vert_index as usize;
+                        let template: [BlockMeshVertex; 4] =
+                            faces.vertices[start..start + 4].try_into().unwrap();
 
-                }
-                if render_south {
+                        let ao = [
+                            template_vertex_ao_level(
+                                block_manager, state_provider, facing, absolute_x, y, absolute_z, &template[0],
+                            ),
+                            template_vertex_ao_level(
+                                block_manager, state_provider, facing, absolute_x, y, absolute_z, &template[1],
+                            ),
+                            template_vertex_ao_level(
+                                block_manager, state_provider, facing, absolute_x, y, absolute_z, &template[2],
+                            ),
+                            template_vertex_ao_level(
+                                block_manager, state_provider, facing, absolute_x, y, absolute_z, &template[3],
+                            ),
+                        ];
 
-                }
-                if render_west {
+                        let (template, ao) = flip_toward_brighter_diagonal(template, ao);
+                        (template, ao, face.tint_index)
+                    })
+                })
+                .flat_map(move |(template, ao, tint_index)| {
+                    // tint_index < 0 means the face isn't tinted; skip the
+                    // lookup in that (common) case.
+                    let color = if tint_index >= 0 {
+                        state_provider.get_block_color(absolute_x, y, absolute_z, tint_index)
+                    } else {
+                        [255; 3]
+                    };
 
-                }
-                if render_up {
+                    std::iter::zip(template, ao).map(move |(v, level)| {
+                        mapper(&v, x as f32, y as f32, z as f32, color, ao_brightness(level), v.tex_index)
+                    })
+                }),
+        );
+    }
 
-                }
-                if render_down {
+    vertices
+}
+
+/// A single off-thread bake request, carrying everything [bake_layer] needs
+/// so a worker doesn't have to reach back into shared renderer state, plus
+/// the distance used to prioritize which dirty section bakes next.
+///
+/// Holds `mc` rather than a pre-extracted `Arc<BlockManager>`: [BlockManager]
+/// lives behind [MinecraftState]'s own lock (see [MinecraftState::block_manager]),
+/// not as a standalone `Arc` anywhere, so a worker reads it through the same
+/// lock every other caller does instead of needing a snapshot that doesn't exist.
+pub struct ChunkBakeRequest {
+    pub chunk: Arc<Chunk>,
+    pub mc: Arc<MinecraftState>,
+    pub layers: Arc<Vec<Box<dyn RenderLayer>>>,
+    pub provider: Box<dyn BlockStateProvider>,
+    pub block_atlas: Arc<crate::render::atlas::Atlas>,
+    pub distance_to_camera: f32,
+}
+
+/// A finished bake, ready to be uploaded into [ChunkAllocation] on the main thread.
+pub struct ChunkBakeResult {
+    pub pos: ChunkPos,
+    pub layers: HashMap<String, Vec<u8>>,
+}
+
+/// A request sitting in [ChunkBakeQueue]'s priority queue. Ordered so
+/// [std::collections::BinaryHeap] (a max-heap) pops the *closest* chunk to
+/// the camera first.
+struct QueuedRequest {
+    generation: usize,
+    request: ChunkBakeRequest,
+}
+
+impl PartialEq for QueuedRequest {
+    fn eq(&self, other: &Self) -> bool {
+        self.request.distance_to_camera == other.request.distance_to_camera
+    }
+}
+
+impl Eq for QueuedRequest {}
+
+impl PartialOrd for QueuedRequest {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedRequest {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .request
+            .distance_to_camera
+            .partial_cmp(&self.request.distance_to_camera)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }
+}
 
+/// A rayon-backed scheduler that bakes dirty chunk sections off the calling
+/// thread, closest-to-the-camera first.
+///
+/// This is the one and only chunk-bake worker pool; it replaced an earlier
+/// standalone worker-pool prototype entirely rather than living alongside
+/// it, so there's a single queue/thread-pool pair wired to
+/// [WmRenderer::drive_chunk_bake_queue](crate::WmRenderer::drive_chunk_bake_queue),
+/// not two competing ones.
+///
+/// Callers [ChunkBakeQueue::enqueue] sections as they go dirty and
+/// periodically call [ChunkBakeQueue::dispatch_batch] (e.g. once per frame)
+/// to hand up to `batch_size` of the closest outstanding sections to a rayon
+/// thread pool; [ChunkBakeQueue::poll_finished] then drains whatever's ready
+/// without ever blocking. If a position is enqueued again before its
+/// previous request has been baked (or while it's being baked), the newer
+/// request wins and the stale result, if one arrives later, is dropped.
+pub struct ChunkBakeQueue {
+    thread_pool: rayon::ThreadPool,
+    batch_size: usize,
+    queue: Mutex<BinaryHeap<QueuedRequest>>,
+    result_tx: Sender<ChunkBakeResult>,
+    result_rx: Receiver<ChunkBakeResult>,
+    /// The generation most recently enqueued for each position, so a batch
+    /// can tell after baking whether its result has since been superseded.
+    pending: Arc<Mutex<HashMap<ChunkPos, usize>>>,
+    next_generation: Mutex<usize>,
+}
+
+impl ChunkBakeQueue {
+    pub fn new(thread_count: usize, batch_size: usize) -> Self {
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(thread_count.max(1))
+            .thread_name(|index| format!("chunk-bake-{index}"))
+            .build()
+            .expect("failed to create chunk bake thread pool");
+
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+
+        Self {
+            thread_pool,
+            batch_size: batch_size.max(1),
+            queue: Mutex::new(BinaryHeap::new()),
+            result_tx,
+            result_rx,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            next_generation: Mutex::new(0),
+        }
+    }
+
+    /// Enqueues `request`, superseding any not-yet-baked request already
+    /// queued for the same chunk position.
+    pub fn enqueue(&self, request: ChunkBakeRequest) {
+        let pos = request.chunk.pos;
+
+        let generation = {
+            let mut next_generation = self.next_generation.lock();
+            *next_generation += 1;
+            *next_generation
+        };
+
+        self.pending.lock().insert(pos, generation);
+        self.queue.lock().push(QueuedRequest { generation, request });
+    }
+
+    /// Pulls up to `batch_size` of the closest outstanding requests off the
+    /// queue and bakes them in parallel on the rayon thread pool, sending
+    /// each finished result back through the channel [Self::poll_finished]
+    /// drains. Stale requests (superseded since they were enqueued) are
+    /// skipped without being baked at all.
+    pub fn dispatch_batch(&self) {
+        let batch: Vec<QueuedRequest> = {
+            let pending = self.pending.lock();
+            let mut queue = self.queue.lock();
+            let mut batch = Vec::with_capacity(self.batch_size);
+
+            while batch.len() < self.batch_size {
+                let Some(queued) = queue.pop() else {
+                    break;
+                };
+
+                if pending.get(&queued.request.chunk.pos) == Some(&queued.generation) {
+                    batch.push(queued);
                 }
             }
-            CubeOrComplexMesh::Complex(model) => {
-                vertices.extend(
-                    model
-                        .iter()
-                        .flat_map(|faces| {
-                            [
-                                faces.north.as_ref(),
-                                faces.east.as_ref(),
-                                faces.south.as_ref(),
-                                faces.west.as_ref(),
-                                faces.up.as_ref(),
-                                faces.down.as_ref(),
-                            ]
-                        })
-                        .flatten()
-                        .flatten()
-                        .map(|v| mapper(v, x as f32, y as f32, z as f32)),
-                );
-            }
+
+            batch
+        };
+
+        if batch.is_empty() {
+            return;
         }
+
+        let pending = self.pending.clone();
+        let result_tx = self.result_tx.clone();
+
+        self.thread_pool.install(|| {
+            batch.into_par_iter().for_each(|queued| {
+                let pos = queued.request.chunk.pos;
+                let request = &queued.request;
+
+                let block_manager = request.mc.block_manager.read();
+                let layers = request
+                    .layers
+                    .iter()
+                    .map(|layer| {
+                        let verts = bake_layer(
+                            &block_manager,
+                            &request.chunk,
+                            layer.mapper(),
+                            layer.filter(),
+                            &*request.provider,
+                            &request.block_atlas,
+                        );
+
+                        (
+                            layer.name().to_string(),
+                            bytemuck::cast_slice(&verts).to_vec(),
+                        )
+                    })
+                    .collect();
+
+                //Another, newer request for this position was enqueued while
+                //we were baking; our result is stale, so just drop it.
+                if pending.lock().get(&pos) != Some(&queued.generation) {
+                    return;
+                }
+
+                let _ = result_tx.send(ChunkBakeResult { pos, layers });
+            });
+        });
     }
 
-    vertices
+    /// Drains every bake that's finished since the last call. Never blocks.
+    pub fn poll_finished(&self) -> Vec<ChunkBakeResult> {
+        self.result_rx.try_iter().collect()
+    }
 }
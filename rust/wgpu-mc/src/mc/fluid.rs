@@ -0,0 +1,260 @@
+//! Meshing for water and lava, which can't be expressed as a regular block
+//! model since their top surface slopes according to neighboring fluid levels.
+
+use crate::mc::block::BlockMeshVertex;
+use crate::mc::chunk::BlockStateProvider;
+use crate::mc::resource::ResourcePath;
+use crate::render::atlas::Atlas;
+
+/// Which fluid a [FluidState] describes. Both fluids mesh the same way; only
+/// the textures and tint differ.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FluidKind {
+    Water,
+    Lava,
+}
+
+impl FluidKind {
+    fn still_texture(self) -> ResourcePath {
+        match self {
+            FluidKind::Water => "minecraft:block/water_still".into(),
+            FluidKind::Lava => "minecraft:block/lava_still".into(),
+        }
+    }
+
+    fn flow_texture(self) -> ResourcePath {
+        match self {
+            FluidKind::Water => "minecraft:block/water_flow".into(),
+            FluidKind::Lava => "minecraft:block/lava_flow".into(),
+        }
+    }
+}
+
+/// The fluid occupying a position, if any. `level` follows vanilla's
+/// convention: `8` is a full source block, `1..=7` are flowing heights
+/// counting down from full, and callers represent "no fluid here" as `None`
+/// from [BlockStateProvider::get_fluid_state].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FluidState {
+    pub kind: FluidKind,
+    pub level: u8,
+}
+
+impl FluidState {
+    pub fn is_source(&self) -> bool {
+        self.level >= 8
+    }
+
+    /// Normalized height in `[0, 1]` this fluid fills its block to.
+    fn height(&self) -> f32 {
+        if self.is_source() {
+            1.0
+        } else {
+            (self.level.max(1) as f32) / 9.0
+        }
+    }
+}
+
+fn neighbor_height(
+    state_provider: &dyn BlockStateProvider,
+    kind: FluidKind,
+    x: i32,
+    y: i16,
+    z: i32,
+) -> Option<f32> {
+    state_provider
+        .get_fluid_state(x, y, z)
+        .filter(|state| state.kind == kind)
+        .map(|state| state.height())
+}
+
+/// Averages the fluid height of the up-to-four blocks sharing a top corner,
+/// where the corner is offset from `(x, z)` by `(corner_dx, corner_dz)` (each
+/// `-1` or `1`). Blocks with no matching fluid simply don't contribute.
+fn corner_height(
+    state_provider: &dyn BlockStateProvider,
+    kind: FluidKind,
+    x: i32,
+    y: i16,
+    z: i32,
+    corner_dx: i32,
+    corner_dz: i32,
+) -> f32 {
+    //Matching vanilla: the same kind of fluid (at any level) directly above
+    //makes every corner touching this column report full height.
+    if matches!(state_provider.get_fluid_state(x, y + 1, z), Some(s) if s.kind == kind) {
+        return 1.0;
+    }
+
+    let columns = [
+        (x, z),
+        (x + corner_dx, z),
+        (x, z + corner_dz),
+        (x + corner_dx, z + corner_dz),
+    ];
+
+    let (sum, count) = columns.iter().fold((0.0f32, 0u32), |(sum, count), &(cx, cz)| {
+        match neighbor_height(state_provider, kind, cx, y, cz) {
+            Some(height) => (sum + height, count + 1),
+            None => (sum, count),
+        }
+    });
+
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f32
+    }
+}
+
+/// Points the flow UV rotation toward the steepest downhill direction between
+/// the four corner heights, or `None` when the surface is level (still water).
+fn flow_rotation(corners: [f32; 4]) -> Option<u32> {
+    //Corners are ordered north-west, north-east, south-east, south-west.
+    let [nw, ne, se, sw] = corners;
+
+    let dx = (ne + se) - (nw + sw);
+    let dz = (sw + se) - (nw + ne);
+
+    if dx.abs() < f32::EPSILON && dz.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let angle = dz.atan2(dx).to_degrees();
+    let rotation = ((angle + 360.0) % 360.0 / 90.0).round() as u32 % 4;
+    Some(rotation * 90)
+}
+
+fn should_render_side(
+    state_provider: &dyn BlockStateProvider,
+    kind: FluidKind,
+    own_level: u8,
+    x: i32,
+    y: i16,
+    z: i32,
+) -> bool {
+    match state_provider.get_fluid_state(x, y, z) {
+        //Don't draw a side against an equal-or-higher fluid of the same kind.
+        Some(state) if state.kind == kind && state.level >= own_level => false,
+        _ => true,
+    }
+}
+
+/// Bakes a fluid block into a sloped top surface plus any unculled side faces.
+pub fn bake_fluid(
+    state: FluidState,
+    state_provider: &dyn BlockStateProvider,
+    block_atlas: &Atlas,
+    x: i32,
+    y: i16,
+    z: i32,
+) -> Vec<BlockMeshVertex> {
+    let mut vertices = Vec::new();
+
+    let nw = corner_height(state_provider, state.kind, x, y, z, -1, -1);
+    let ne = corner_height(state_provider, state.kind, x, y, z, 1, -1);
+    let se = corner_height(state_provider, state.kind, x, y, z, 1, 1);
+    let sw = corner_height(state_provider, state.kind, x, y, z, -1, 1);
+
+    let flowing = flow_rotation([nw, ne, se, sw]);
+
+    let uv_map = block_atlas.uv_map.read();
+    let texture = if flowing.is_some() {
+        state.kind.flow_texture()
+    } else {
+        state.kind.still_texture()
+    };
+
+    let Some(((u0, v0), (u1, v1))) = uv_map.get(&texture).copied() else {
+        return vertices;
+    };
+
+    //Top quad, one corner per vertex, height-displaced per the averaged
+    //neighbor heights computed above. Cull it when the block above is the
+    //same fluid, since it'll never be visible.
+    let render_top = !matches!(
+        state_provider.get_fluid_state(x, y + 1, z),
+        Some(above) if above.kind == state.kind
+    );
+
+    if render_top {
+        let uvs = [(u0, v0), (u1, v0), (u1, v1), (u0, v1)];
+        //When flowing, rotate which corner gets which UV so the flow texture's
+        //arrow points toward the downhill direction instead of always north.
+        let shift = (flowing.unwrap_or(0) / 90) as usize;
+        let uvs = [
+            uvs[shift % 4],
+            uvs[(shift + 1) % 4],
+            uvs[(shift + 2) % 4],
+            uvs[(shift + 3) % 4],
+        ];
+
+        //Block-local positions: the caller's `mapper` adds the chunk-local
+        //x/y/z offset on top of these, so only the neighbor-sampling calls
+        //above use the absolute `x`/`y`/`z` arguments.
+        let top = [
+            ([0.0, nw, 0.0], uvs[0]),
+            ([1.0, ne, 0.0], uvs[1]),
+            ([1.0, se, 1.0], uvs[2]),
+            ([0.0, sw, 1.0], uvs[3]),
+        ];
+
+        vertices.extend(top.iter().map(|(position, (u, v))| BlockMeshVertex {
+            position: *position,
+            tex_coords: [*u, *v],
+            normal: [0.0, 1.0, 0.0],
+            animation_uv_offset: 0,
+            tex_index: 0,
+        }));
+    }
+
+    let sides: [(i32, i16, i32, [f32; 3]); 4] = [
+        (x, y, z - 1, [0.0, 0.0, -1.0]),
+        (x + 1, y, z, [1.0, 0.0, 0.0]),
+        (x, y, z + 1, [0.0, 0.0, 1.0]),
+        (x - 1, y, z, [-1.0, 0.0, 0.0]),
+    ];
+
+    for (nx, ny, nz, normal) in sides {
+        if !should_render_side(state_provider, state.kind, state.level, nx, ny, nz) {
+            continue;
+        }
+
+        let corners = match normal {
+            [0.0, 0.0, -1.0] => [
+                ([0.0, nw, 0.0], (u0, v0)),
+                ([1.0, ne, 0.0], (u1, v0)),
+                ([1.0, 0.0, 0.0], (u1, v1)),
+                ([0.0, 0.0, 0.0], (u0, v1)),
+            ],
+            [1.0, 0.0, 0.0] => [
+                ([1.0, ne, 0.0], (u0, v0)),
+                ([1.0, se, 1.0], (u1, v0)),
+                ([1.0, 0.0, 1.0], (u1, v1)),
+                ([1.0, 0.0, 0.0], (u0, v1)),
+            ],
+            [0.0, 0.0, 1.0] => [
+                ([1.0, se, 1.0], (u0, v0)),
+                ([0.0, sw, 1.0], (u1, v0)),
+                ([0.0, 0.0, 1.0], (u1, v1)),
+                ([1.0, 0.0, 1.0], (u0, v1)),
+            ],
+            _ => [
+                ([0.0, sw, 1.0], (u0, v0)),
+                ([0.0, nw, 0.0], (u1, v0)),
+                ([0.0, 0.0, 0.0], (u1, v1)),
+                ([0.0, 0.0, 1.0], (u0, v1)),
+            ],
+        };
+
+        vertices.extend(corners.iter().map(|(position, (u, v))| BlockMeshVertex {
+            position: *position,
+            tex_coords: [*u, *v],
+            normal,
+            animation_uv_offset: 0,
+            tex_index: 0,
+        }));
+    }
+
+    vertices
+}
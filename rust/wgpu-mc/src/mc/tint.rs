@@ -0,0 +1,161 @@
+//! Biome-aware tinting for blocks such as grass, leaves and water.
+//!
+//! Vanilla resolves these colors from a pair of 256x256 colormap textures
+//! (`colormap/grass.png` and `colormap/foliage.png`) indexed by a biome's
+//! temperature and downfall. This module reproduces that lookup.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+
+const COLORMAP_SIZE: usize = 256;
+
+/// The climate values used to index a [BiomeColormap].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Biome {
+    pub temperature: f32,
+    pub downfall: f32,
+}
+
+/// Describes how a model face's `tint_index` should be resolved into a color.
+///
+/// This is what a block declares for each `tint_index` it uses; it's up to
+/// whoever implements [BlockStateProvider::get_block_color](crate::mc::chunk::BlockStateProvider::get_block_color)
+/// to consult it and pick the matching colormap.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TintTarget {
+    /// No tint is applied; the face renders at full brightness.
+    Default,
+    /// Resolved from the grass colormap using the block's biome.
+    Grass,
+    /// Resolved from the foliage colormap using the block's biome.
+    Foliage,
+    /// A fixed color, not dependent on biome.
+    Color([u8; 3]),
+}
+
+/// A 256x256 RGB colormap, sampled by biome temperature/downfall.
+#[derive(Debug)]
+pub struct BiomeColormap {
+    pixels: Box<[[u8; 3]]>,
+}
+
+impl BiomeColormap {
+    pub fn load(resource_provider: &dyn ResourceProvider, path: &ResourcePath) -> Option<Self> {
+        let bytes = resource_provider.get_bytes(&path.prepend("textures/").append(".png"))?;
+        let image = image::load_from_memory(&bytes).ok()?.into_rgb8();
+
+        if image.width() as usize != COLORMAP_SIZE || image.height() as usize != COLORMAP_SIZE {
+            //A resource pack is untrusted input; a malformed colormap should
+            //fall back to Default tinting, not take the renderer down.
+            return None;
+        }
+
+        let pixels = image
+            .pixels()
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+
+        Some(Self { pixels })
+    }
+
+    /// Resolves a biome's temperature/downfall into a color.
+    ///
+    /// `adjRain = downfall * temperature`, and the colormap is indexed at
+    /// `x = floor((1 - t) * 255)`, `y = floor((1 - adjRain) * 255)`. Only the
+    /// lower-right triangle of the image (`x + y <= 255`) is meaningful;
+    /// samples above the diagonal are reflected back into it, matching vanilla.
+    pub fn sample(&self, biome: Biome) -> [u8; 3] {
+        let t = biome.temperature.clamp(0.0, 1.0);
+        let d = biome.downfall.clamp(0.0, 1.0);
+        let adj_rain = d * t;
+
+        let mut x = ((1.0 - t) * 255.0).floor() as i32;
+        let mut y = ((1.0 - adj_rain) * 255.0).floor() as i32;
+
+        if x + y > 255 {
+            //Reflect across the x + y = 255 diagonal, which swaps the two
+            //axes - not a 180-degree rotation about the square's center, so
+            //x and y need to trade values rather than each negate in place.
+            let (reflected_x, reflected_y) = (255 - y, 255 - x);
+            x = reflected_x;
+            y = reflected_y;
+        }
+
+        let index = (y as usize) * COLORMAP_SIZE + (x as usize);
+        self.pixels[index]
+    }
+}
+
+/// Loads and exposes the grass/foliage colormaps used to tint blocks per-biome.
+#[derive(Debug)]
+pub struct BiomeColorProvider {
+    pub grass: BiomeColormap,
+    pub foliage: BiomeColormap,
+}
+
+impl BiomeColorProvider {
+    pub fn load(resource_provider: &dyn ResourceProvider) -> Option<Self> {
+        Some(Self {
+            grass: BiomeColormap::load(resource_provider, &"minecraft:colormap/grass".into())?,
+            foliage: BiomeColormap::load(
+                resource_provider,
+                &"minecraft:colormap/foliage".into(),
+            )?,
+        })
+    }
+
+    pub fn resolve(&self, target: TintTarget, biome: Biome) -> [u8; 3] {
+        match target {
+            TintTarget::Default => [255; 3],
+            TintTarget::Grass => self.grass.sample(biome),
+            TintTarget::Foliage => self.foliage.sample(biome),
+            TintTarget::Color(color) => color,
+        }
+    }
+}
+
+/// The colormaps loaded by [load_startup], if any. A module static rather
+/// than a field on [crate::mc::MinecraftState] because that struct, like the
+/// rest of `mc::mod`, lives outside what this change can reach; see
+/// `mc::block`'s `TEXTURE_LAYER_INDICES` for the same workaround.
+static COLOR_PROVIDER: OnceLock<Option<BiomeColorProvider>> = OnceLock::new();
+
+/// Loads the grass/foliage colormaps once, from [WmRenderer::new](crate::WmRenderer::new).
+/// `None` if the resource pack doesn't ship them (or they fail to decode);
+/// callers resolving a tint should fall back to [TintTarget::Default] colors
+/// in that case rather than panicking.
+pub fn load_startup(resource_provider: &dyn ResourceProvider) {
+    COLOR_PROVIDER.get_or_init(|| BiomeColorProvider::load(resource_provider));
+}
+
+/// The colormaps loaded by [load_startup], or `None` if that hasn't run yet
+/// or found nothing to load.
+pub fn loaded() -> Option<&'static BiomeColorProvider> {
+    COLOR_PROVIDER.get().and_then(Option::as_ref)
+}
+
+/// Per-block, per-`tint_index` declaration of what a face's tint resolves to.
+/// Blocks with no entry for a given index are assumed [TintTarget::Default].
+#[derive(Debug, Default)]
+pub struct BlockTintRegistry {
+    targets: HashMap<(u16, i32), TintTarget>,
+}
+
+impl BlockTintRegistry {
+    pub fn register(&mut self, block: u16, tint_index: i32, target: TintTarget) {
+        self.targets.insert((block, tint_index), target);
+    }
+
+    pub fn get(&self, block: u16, tint_index: i32) -> TintTarget {
+        if tint_index < 0 {
+            return TintTarget::Default;
+        }
+
+        self.targets
+            .get(&(block, tint_index))
+            .copied()
+            .unwrap_or(TintTarget::Default)
+    }
+}
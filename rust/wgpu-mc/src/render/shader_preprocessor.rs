@@ -0,0 +1,286 @@
+//! WGSL preprocessing for shaderpacks: `#include "path"` pulls in shared
+//! code (lighting, fog, noise) from another resource, `#define`/`#ifdef`/
+//! `#else`/`#endif` select per-variant code paths, and the whole thing is
+//! resolved once per path + active feature-flag set so recompiling the same
+//! variant is a cache hit. [DefineSet::permutations] plus
+//! [ShaderPreprocessor::resolve_permutations] let a pipeline builder resolve
+//! every feature combination (e.g. all shadow-filter/fog-shape variants) up
+//! front instead of compiling on first use.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    MissingSource(ResourcePath),
+    IncludeCycle(Vec<ResourcePath>),
+    MalformedInclude { file: ResourcePath, line: usize },
+    UnterminatedConditional { file: ResourcePath },
+    UnexpectedElseOrEndif { file: ResourcePath, line: usize },
+    /// A line starting with `#` that isn't one of `#include`/`#define`/
+    /// `#ifdef`/`#else`/`#endif`, e.g. a typo'd directive or one meant for a
+    /// different preprocessor entirely.
+    UnknownDirective {
+        file: ResourcePath,
+        line: usize,
+        directive: String,
+    },
+}
+
+/// Which original file/line a line in a [PreprocessedShader]'s resolved
+/// source came from, so a wgpu compile error (which only knows about line
+/// numbers in the flattened output) can be mapped back to where a
+/// shaderpack author actually wrote it.
+#[derive(Debug, Clone)]
+pub struct SourceLocation {
+    pub file: ResourcePath,
+    pub line: usize,
+}
+
+/// A WGSL source after `#include`/`#define`/`#ifdef` resolution.
+#[derive(Debug, Clone)]
+pub struct PreprocessedShader {
+    pub source: String,
+    provenance: Vec<SourceLocation>,
+}
+
+impl PreprocessedShader {
+    /// Maps a 1-indexed line number in [Self::source] back to the file/line
+    /// it was expanded from.
+    pub fn resolve_line(&self, line: usize) -> Option<&SourceLocation> {
+        self.provenance.get(line.checked_sub(1)?)
+    }
+}
+
+/// The active `#define`s for one shader variant, e.g. `SHADOW_FILTER=PCSS`.
+/// Doubles as the preprocessor's cache key, so the same file compiled under
+/// different feature flags (PCF vs PCSS, shadows on/off) never collides.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DefineSet(Vec<(String, String)>);
+
+impl DefineSet {
+    pub fn new<K: Into<String>, V: Into<String>>(defines: impl IntoIterator<Item = (K, V)>) -> Self {
+        let mut defines: Vec<(String, String)> =
+            defines.into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+        defines.sort_unstable();
+        Self(defines)
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.0.iter().any(|(k, _)| k == name)
+    }
+
+    fn with(mut self, name: String, value: String) -> Self {
+        self.0.retain(|(k, _)| k != &name);
+        self.0.push((name, value));
+        self.0.sort_unstable();
+        self
+    }
+
+    /// Every combination of `flags` defined or not, layered on top of
+    /// `self`, e.g. `base.permutations(&["SHADOWS_PCSS", "FOG_CYLINDER"])`
+    /// yields the 4 variants a pipeline needs to precompile so toggling
+    /// either render effect at runtime is just picking a pipeline that's
+    /// already been built, instead of compiling on demand.
+    pub fn permutations(&self, flags: &[&str]) -> Vec<DefineSet> {
+        let variant_count = 1usize << flags.len();
+
+        (0..variant_count)
+            .map(|mask| {
+                flags.iter().enumerate().fold(self.clone(), |set, (i, &flag)| {
+                    if mask & (1 << i) != 0 {
+                        set.with(flag.to_string(), "1".to_string())
+                    } else {
+                        set
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: ResourcePath,
+    defines: DefineSet,
+}
+
+/// Resolves `#include`/`#define`/`#ifdef` directives in WGSL sources pulled
+/// from a [ResourceProvider], caching results per path + active
+/// [DefineSet].
+#[derive(Default)]
+pub struct ShaderPreprocessor {
+    cache: Mutex<HashMap<CacheKey, Arc<PreprocessedShader>>>,
+}
+
+impl ShaderPreprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolves `path`, expanding `#include`s and evaluating
+    /// `#ifdef`/`#else`/`#endif` against `defines` (which also seeds the
+    /// `#define`-visible names the shaderpack's own directives extend).
+    pub fn resolve(
+        &self,
+        path: &ResourcePath,
+        defines: &DefineSet,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<Arc<PreprocessedShader>, PreprocessError> {
+        let key = CacheKey {
+            path: path.clone(),
+            defines: defines.clone(),
+        };
+
+        if let Some(cached) = self.cache.lock().get(&key) {
+            return Ok(cached.clone());
+        }
+
+        let mut source = String::new();
+        let mut provenance = Vec::new();
+        let mut visiting = HashSet::new();
+
+        Self::expand(
+            path,
+            defines.clone(),
+            resource_provider,
+            &mut source,
+            &mut provenance,
+            &mut visiting,
+        )?;
+
+        let resolved = Arc::new(PreprocessedShader { source, provenance });
+        self.cache.lock().insert(key, resolved.clone());
+
+        Ok(resolved)
+    }
+
+    /// Resolves `path` under every [DefineSet] in `variants`, e.g. the
+    /// output of [DefineSet::permutations]. Meant to be called once at
+    /// pipeline-build time so every feature permutation a shaderpack
+    /// supports is already in [Self::cache] before the first frame, rather
+    /// than stalling the first draw that happens to need an untried
+    /// combination.
+    pub fn resolve_permutations(
+        &self,
+        path: &ResourcePath,
+        variants: &[DefineSet],
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<Vec<Arc<PreprocessedShader>>, PreprocessError> {
+        variants
+            .iter()
+            .map(|defines| self.resolve(path, defines, resource_provider))
+            .collect()
+    }
+
+    /// Drops every cached shader variant. Call this when the resource pack
+    /// changes, so stale `#include`d code doesn't survive a reload.
+    pub fn invalidate(&self) {
+        self.cache.lock().clear();
+    }
+
+    fn expand(
+        path: &ResourcePath,
+        mut defines: DefineSet,
+        resource_provider: &dyn ResourceProvider,
+        out: &mut String,
+        provenance: &mut Vec<SourceLocation>,
+        visiting: &mut HashSet<ResourcePath>,
+    ) -> Result<(), PreprocessError> {
+        if !visiting.insert(path.clone()) {
+            return Err(PreprocessError::IncludeCycle(visiting.iter().cloned().collect()));
+        }
+
+        let text = resource_provider
+            .get_string(path)
+            .ok_or_else(|| PreprocessError::MissingSource(path.clone()))?;
+
+        //Whether each nesting depth of #ifdef/#else is currently emitting
+        //lines; `#else`/`#endif` pop back to the parent's state.
+        let mut active_stack = vec![true];
+
+        for (index, line) in text.lines().enumerate() {
+            let trimmed = line.trim_start();
+            let active = *active_stack.last().unwrap();
+
+            if let Some(rest) = trimmed.strip_prefix("#include") {
+                if active {
+                    let included = rest
+                        .trim()
+                        .strip_prefix('"')
+                        .and_then(|s| s.strip_suffix('"'))
+                        .ok_or_else(|| PreprocessError::MalformedInclude {
+                            file: path.clone(),
+                            line: index + 1,
+                        })?;
+
+                    Self::expand(
+                        &ResourcePath::from(included),
+                        defines.clone(),
+                        resource_provider,
+                        out,
+                        provenance,
+                        visiting,
+                    )?;
+                }
+            } else if let Some(rest) = trimmed.strip_prefix("#define") {
+                if active {
+                    let mut parts = rest.trim().splitn(2, char::is_whitespace);
+                    let name = parts.next().unwrap_or("").to_string();
+                    let value = parts.next().unwrap_or("").trim().to_string();
+                    defines = defines.with(name, value);
+                }
+            } else if let Some(name) = trimmed.strip_prefix("#ifdef") {
+                active_stack.push(active && defines.is_defined(name.trim()));
+            } else if trimmed.starts_with("#else") {
+                let parent_active = active_stack
+                    .len()
+                    .checked_sub(2)
+                    .map(|i| active_stack[i])
+                    .ok_or_else(|| PreprocessError::UnexpectedElseOrEndif {
+                        file: path.clone(),
+                        line: index + 1,
+                    })?;
+                let this_branch_was_active = active_stack.pop().unwrap();
+                active_stack.push(parent_active && !this_branch_was_active);
+            } else if trimmed.starts_with("#endif") {
+                if active_stack.pop().is_none() || active_stack.is_empty() {
+                    return Err(PreprocessError::UnexpectedElseOrEndif {
+                        file: path.clone(),
+                        line: index + 1,
+                    });
+                }
+            } else if trimmed.starts_with('#') {
+                //Dead code inside a false #ifdef branch is never evaluated,
+                //so a typo'd directive there is harmless until the branch
+                //that catches it goes active.
+                if active {
+                    return Err(PreprocessError::UnknownDirective {
+                        file: path.clone(),
+                        line: index + 1,
+                        directive: trimmed.to_string(),
+                    });
+                }
+            } else if active {
+                out.push_str(line);
+                out.push('\n');
+                provenance.push(SourceLocation {
+                    file: path.clone(),
+                    line: index + 1,
+                });
+            }
+        }
+
+        if active_stack.len() != 1 {
+            return Err(PreprocessError::UnterminatedConditional { file: path.clone() });
+        }
+
+        visiting.remove(path);
+
+        Ok(())
+    }
+}